@@ -1,13 +1,18 @@
 use std::ops::{Deref, DerefMut};
 
-use anchor_lang::prelude::{ProgramError, Pubkey};
-use anchor_lang::solana_program::borsh::try_from_slice_unchecked;
-use metaplex_token_metadata::state::{Key as MetaplexKey, Metadata, MAX_METADATA_LEN};
-use metaplex_token_metadata::utils::try_from_slice_checked;
+use anchor_lang::prelude::{borsh, AccountMeta, AnchorDeserialize, ProgramError, Pubkey};
+use anchor_lang::solana_program::instruction::Instruction;
+use metaplex_token_metadata::state::{Key as MetaplexKey, Metadata, EDITION, MAX_METADATA_LEN};
 
 pub use metaplex_token_metadata::state::PREFIX as PDAPrefix;
 pub use metaplex_token_metadata::ID;
 
+/// Instruction index of `FreezeDelegatedAccount` in the Metaplex Token Metadata
+/// program. Newer than the version of the crate this program depends on.
+const FREEZE_DELEGATED_ACCOUNT: u8 = 26;
+/// Instruction index of `ThawDelegatedAccount` in the Metaplex Token Metadata program.
+const THAW_DELEGATED_ACCOUNT: u8 = 27;
+
 #[derive(Clone)]
 pub struct MetaplexTokenMetadata;
 
@@ -27,22 +32,53 @@ impl anchor_lang::Id for MetaplexTokenMetadata {
     }
 }
 
+/// The verified collection of an NFT. Newer than the version of the crate this
+/// program depends on.
+#[derive(Clone, Debug, AnchorDeserialize, PartialEq)]
+pub struct MetadataCollection {
+    pub verified: bool,
+    pub key: Pubkey,
+}
+
 #[derive(Clone)]
-pub struct MetadataAccount(Metadata);
+pub struct MetadataAccount {
+    metadata: Metadata,
+    pub collection: Option<MetadataCollection>,
+}
 
 impl MetadataAccount {
     pub const LEN: usize = MAX_METADATA_LEN;
+
+    fn deserialize(buf: &mut &[u8]) -> Result<Self, ProgramError> {
+        let metadata = Metadata::deserialize(buf)
+            .map_err(|err| ProgramError::BorshIoError(err.to_string()))?;
+
+        //newer versions of the metadata program append the token standard and the
+        //collection. Accounts created before then are zero padded so both are None
+        let collection = Option::<u8>::deserialize(buf)
+            .and_then(|_token_standard| Option::<MetadataCollection>::deserialize(buf))
+            .unwrap_or(None);
+
+        Ok(MetadataAccount {
+            metadata,
+            collection,
+        })
+    }
 }
 
 impl anchor_lang::AccountDeserialize for MetadataAccount {
     fn try_deserialize(buf: &mut &[u8]) -> Result<Self, ProgramError> {
-        try_from_slice_checked(buf, MetaplexKey::MetadataV1, MAX_METADATA_LEN).map(MetadataAccount)
+        if buf.len() != MAX_METADATA_LEN
+            || (buf[0] != MetaplexKey::MetadataV1 as u8
+                && buf[0] != MetaplexKey::Uninitialized as u8)
+        {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        MetadataAccount::try_deserialize_unchecked(buf)
     }
 
     fn try_deserialize_unchecked(buf: &mut &[u8]) -> Result<Self, ProgramError> {
-        let metadata: Metadata = try_from_slice_unchecked(buf)
-            .map_err(|err| ProgramError::BorshIoError(err.to_string()))?;
-        Ok(MetadataAccount(metadata))
+        MetadataAccount::deserialize(buf)
     }
 }
 
@@ -63,12 +99,89 @@ impl Deref for MetadataAccount {
     type Target = Metadata;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.metadata
     }
 }
 
 impl DerefMut for MetadataAccount {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        &mut self.metadata
+    }
+}
+
+/// Returns the address of the (master) edition PDA for the given mint
+pub fn get_edition_address(mint: &Pubkey) -> Pubkey {
+    let (edition, _) = Pubkey::find_program_address(
+        &[
+            PDAPrefix.as_bytes(),
+            &ID.to_bytes(),
+            &mint.to_bytes(),
+            EDITION.as_bytes(),
+        ],
+        &ID,
+    );
+    edition
+}
+
+/// Returns true if the account data is a Metaplex master edition or print edition
+pub fn is_edition(data: &[u8]) -> bool {
+    let edition_keys = [
+        MetaplexKey::MasterEditionV1 as u8,
+        MetaplexKey::MasterEditionV2 as u8,
+        MetaplexKey::EditionV1 as u8,
+    ];
+    matches!(data.first(), Some(key) if edition_keys.contains(key))
+}
+
+/// Freezes a token account using its delegate. The edition of the mint is
+/// the freeze authority, so only the metadata program can freeze the account.
+pub fn freeze_delegated_account(
+    delegate: Pubkey,
+    token_account: Pubkey,
+    edition: Pubkey,
+    mint: Pubkey,
+) -> Instruction {
+    delegated_account_instruction(
+        FREEZE_DELEGATED_ACCOUNT,
+        delegate,
+        token_account,
+        edition,
+        mint,
+    )
+}
+
+/// Thaws a token account previously frozen with `freeze_delegated_account`
+pub fn thaw_delegated_account(
+    delegate: Pubkey,
+    token_account: Pubkey,
+    edition: Pubkey,
+    mint: Pubkey,
+) -> Instruction {
+    delegated_account_instruction(
+        THAW_DELEGATED_ACCOUNT,
+        delegate,
+        token_account,
+        edition,
+        mint,
+    )
+}
+
+fn delegated_account_instruction(
+    instruction: u8,
+    delegate: Pubkey,
+    token_account: Pubkey,
+    edition: Pubkey,
+    mint: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: ID,
+        accounts: vec![
+            AccountMeta::new(delegate, true),
+            AccountMeta::new(token_account, false),
+            AccountMeta::new_readonly(edition, false),
+            AccountMeta::new_readonly(mint, false),
+            AccountMeta::new_readonly(spl_token::ID, false),
+        ],
+        data: vec![instruction],
     }
 }
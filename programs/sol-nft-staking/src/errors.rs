@@ -58,4 +58,108 @@ pub enum StakingError {
 
     #[msg("The Metaplex Metadata account did not have the expected creators")]
     InvalidMetadataCreators,
+
+    #[msg("The reward calculation overflowed")]
+    RewardOverflow,
+
+    #[msg(
+        "The provided reward vault is not the reward authority's token account for the reward mint"
+    )]
+    InvalidRewardVault,
+
+    #[msg("The rewarder does not pay out rewards from a vault")]
+    RewarderNotVaultMode,
+
+    #[msg("The provided NFT vault is not the stake account's token account for the NFT mint")]
+    InvalidNFTVault,
+
+    #[msg("The provided edition account is not the Metaplex edition PDA for the NFT mint")]
+    InvalidNFTEdition,
+
+    #[msg("The provided NFT token account is not delegated to the stake account")]
+    InvalidNFTDelegate,
+
+    #[msg("The provided stake receipt is not for the stake account")]
+    InvalidStakeReceipt,
+
+    #[msg("The lockup tiers must contain between 1 and 4 tiers with non-negative durations")]
+    InvalidLockupTiers,
+
+    #[msg("The rewarder does not have the requested lockup tier")]
+    InvalidLockupTier,
+
+    #[msg("The NFT can't be unstaked until its lockup period has ended")]
+    StakeLocked,
+
+    #[msg("The unbonding period must not be negative and the penalty must not exceed 10,000 basis points")]
+    InvalidUnbondingConfig,
+
+    #[msg("The NFT can't be withdrawn until its unbonding period has ended")]
+    StillUnbonding,
+
+    #[msg("The weight proof does not match the rewarder's weights root")]
+    InvalidWeightProof,
+
+    #[msg("The NFT is not a verified member of the rewarder's Metaplex collection")]
+    InvalidMetadataCollection,
+
+    #[msg("A rewarder can have at most 3 additional update authorities")]
+    InvalidMetadataPolicy,
+
+    #[msg("The matching creators in the metadata are not verified")]
+    UnverifiedMetadataCreators,
+
+    #[msg("The provided edition account is not a Metaplex master or print edition")]
+    InvalidNFTEditionAccount,
+
+    #[msg("The NFT mint must have 0 decimals")]
+    InvalidNFTMintDecimals,
+
+    #[msg("The NFT mint can still mint tokens")]
+    InvalidNFTMintAuthority,
+
+    #[msg("The allowlist proof does not match the rewarder's allowlist root")]
+    InvalidAllowlistProof,
+
+    #[msg("The NFT mint has been blocked by the rewarder authority")]
+    MintBlocked,
+
+    #[msg("A rewarder can't have more than 5 creators")]
+    InvalidCreators,
+
+    #[msg("The signer is not the pending authority of the rewarder")]
+    InvalidPendingAuthority,
+
+    #[msg("Staking is paused for this rewarder")]
+    StakingPaused,
+
+    #[msg("Claiming is paused for this rewarder")]
+    ClaimingPaused,
+
+    #[msg("The emission schedule must end after it starts and can only halve from a start time")]
+    InvalidEmissionSchedule,
+
+    #[msg("The rewarder has paid out its entire emission cap")]
+    EmissionCapReached,
+
+    #[msg("A rewarder can have up to 3 reward streams, each with its own reward mint")]
+    InvalidRewardStream,
+
+    #[msg("The reward stream accounts are missing or don't match the rewarder's reward streams")]
+    InvalidRewardStreamAccounts,
+
+    #[msg("The account is already on the latest layout")]
+    AlreadyMigrated,
+
+    #[msg("The stake account has no NFTs left from before stake receipts existed")]
+    NoV0StakedNfts,
+
+    #[msg("A staking count, weight or timestamp is out of range")]
+    ArithmeticOverflow,
+
+    #[msg("Only blocked mints can be force unstaked")]
+    MintNotBlocked,
+
+    #[msg("The unpaid rewards don't cover the penalty for withdrawing early")]
+    PenaltyNotCovered,
 }
@@ -1,18 +1,36 @@
+//anchor's macros expand to checks for cfg features of its own, which newer
+//compilers flag as unexpected in this crate and older ones don't know about
+#![allow(unknown_lints, unexpected_cfgs)]
+
 use anchor_lang::prelude::*;
 
 pub mod anchor_metaplex;
 pub mod errors;
+pub mod merkle;
 pub mod state;
 
-use anchor_metaplex::MetadataAccount;
-use anchor_spl::associated_token::get_associated_token_address;
-use anchor_spl::token::{self, Mint, MintTo, SetAuthority, Token, TokenAccount};
+use anchor_lang::solana_program::entrypoint::MAX_PERMITTED_DATA_INCREASE;
+use anchor_lang::solana_program::hash::hash;
+use anchor_lang::solana_program::program::{invoke, invoke_signed};
+use anchor_lang::solana_program::program_option::COption;
+use anchor_lang::solana_program::pubkey::MAX_SEED_LEN;
+use anchor_lang::solana_program::system_instruction;
+use anchor_lang::AccountsClose;
+use anchor_metaplex::{MetadataAccount, MetaplexTokenMetadata};
+use anchor_spl::associated_token::{self, get_associated_token_address, AssociatedToken};
+use anchor_spl::token::{
+    self, Approve, CloseAccount, Mint, MintTo, SetAuthority, Token, TokenAccount, Transfer,
+};
 use errors::*;
+use metaplex_token_metadata::state::{Creator, MAX_CREATOR_LIMIT};
 use spl_token::instruction::AuthorityType;
 use state::*;
+use std::convert::TryFrom;
 
 const REWARDER_PREFIX: &[u8] = b"rewarder";
 const ACCOUNT_PREFIX: &[u8] = b"stake_account";
+const RECEIPT_PREFIX: &[u8] = b"stake_receipt";
+const BLOCKED_PREFIX: &[u8] = b"blocked_mint";
 
 declare_id!("3zPPaZhN3tAkSJhjcEcyT7kAM6b2stQmJf65Fw9sMZa3");
 
@@ -20,6 +38,7 @@ declare_id!("3zPPaZhN3tAkSJhjcEcyT7kAM6b2stQmJf65Fw9sMZa3");
 pub mod sol_nft_staking {
 
     use super::*;
+    #[allow(clippy::too_many_arguments)]
     pub fn initialize_rewarder(
         ctx: Context<InitializeRewarder>,
         _rewarder_bump: u8,
@@ -29,9 +48,17 @@ pub mod sol_nft_staking {
         creators: Vec<CreatorStruct>,
         nft_update_authority: Pubkey,
         enforce_metadata: bool,
+        reward_mode: RewardMode,
+        custody_mode: CustodyMode,
+        collection_strategy: CollectionStrategy,
+        pool_id: u64,
     ) -> ProgramResult {
         let rewarder = &mut ctx.accounts.rewarder;
 
+        if creators.len() > MAX_CREATOR_LIMIT {
+            return Err(StakingError::InvalidCreators.into());
+        }
+
         rewarder.authority = ctx.accounts.authority.key();
         rewarder.reward_mint = ctx.accounts.reward_mint.key();
         rewarder.reward_authority_bump = reward_authority_bump;
@@ -41,18 +68,317 @@ pub mod sol_nft_staking {
         rewarder.collection = collection;
         rewarder.total_staked = 0;
         rewarder.enforce_metadata = enforce_metadata;
+        rewarder.accrued_reward_per_nft = 0;
+        rewarder.last_accrued = ctx.accounts.clock.unix_timestamp;
+        rewarder.reward_mode = reward_mode;
+        rewarder.custody_mode = custody_mode;
+        rewarder.total_weight = 0;
+        rewarder.lockup_tiers = vec![LockupTier {
+            duration: 0,
+            multiplier: BASE_MULTIPLIER,
+        }];
+        rewarder.unbonding_period = 0;
+        rewarder.early_unstake_penalty_bps = 0;
+        rewarder.weights_root = [0; 32];
+        rewarder.collection_strategy = collection_strategy;
+        rewarder.metadata_policy = MetadataPolicy::default();
+        rewarder.additional_update_authorities = vec![];
+        rewarder.allowlist_root = [0; 32];
+        rewarder.pending_authority = Pubkey::default();
+        rewarder.paused = PauseFlags::default();
+        rewarder.schedule = EmissionSchedule::default();
+        rewarder.emission_cap = 0;
+        rewarder.total_emitted = 0;
+        rewarder.reward_streams = vec![];
+        rewarder.pool_id = pool_id;
+        rewarder.version = REWARDER_VERSION;
 
         Ok(())
     }
 
     pub fn update_reward_rate(ctx: Context<UpdateRewardRate>, new_rate: u64) -> ProgramResult {
         let rewarder = &mut ctx.accounts.rewarder;
+        let clock = &ctx.accounts.clock;
 
+        // Accrue rewards at the old rate so the new rate only applies from now on
+        update_accrued_reward(rewarder, clock.unix_timestamp)?;
         rewarder.reward_rate = new_rate;
 
         Ok(())
     }
 
+    pub fn add_reward_stream(
+        ctx: Context<AddRewardStream>,
+        reward_authority_bump: u8,
+        reward_rate: u64,
+        reward_mode: RewardMode,
+    ) -> ProgramResult {
+        let rewarder = &mut ctx.accounts.rewarder;
+        let reward_mint = ctx.accounts.reward_mint.key();
+        let clock = &ctx.accounts.clock;
+
+        let duplicate = reward_mint == rewarder.reward_mint
+            || rewarder
+                .reward_streams
+                .iter()
+                .any(|stream| stream.reward_mint == reward_mint);
+        if duplicate || rewarder.reward_streams.len() >= MAX_REWARD_STREAMS {
+            return Err(StakingError::InvalidRewardStream.into());
+        }
+
+        //the stream starts from nothing now, matching the empty checkpoints of existing stake accounts
+        update_accrued_reward(rewarder, clock.unix_timestamp)?;
+        rewarder.reward_streams.push(RewardStream {
+            reward_mint,
+            reward_authority_bump,
+            reward_rate,
+            reward_mode,
+            accrued_reward_per_nft: 0,
+        });
+
+        Ok(())
+    }
+
+    pub fn update_stream_rate(
+        ctx: Context<UpdateRewardRate>,
+        stream: u8,
+        new_rate: u64,
+    ) -> ProgramResult {
+        let rewarder = &mut ctx.accounts.rewarder;
+        let clock = &ctx.accounts.clock;
+
+        if stream as usize >= rewarder.reward_streams.len() {
+            return Err(StakingError::InvalidRewardStream.into());
+        }
+
+        // Accrue rewards at the old rate so the new rate only applies from now on
+        update_accrued_reward(rewarder, clock.unix_timestamp)?;
+        rewarder.reward_streams[stream as usize].reward_rate = new_rate;
+
+        Ok(())
+    }
+
+    pub fn update_creators(
+        ctx: Context<UpdateRewarderCreators>,
+        creators: Vec<CreatorStruct>,
+    ) -> ProgramResult {
+        let rewarder = &mut ctx.accounts.rewarder;
+
+        if creators.len() > MAX_CREATOR_LIMIT {
+            return Err(StakingError::InvalidCreators.into());
+        }
+
+        //rewarders are only allocated space for the creators they were created with
+        let new_len = NftStakeRewarder::calculate_len(creators.len(), &rewarder.collection);
+        grow_account(
+            &rewarder.to_account_info(),
+            &ctx.accounts.authority,
+            &ctx.accounts.system_program.to_account_info(),
+            &ctx.accounts.rent,
+            new_len,
+        )?;
+
+        rewarder.creators = creators;
+
+        Ok(())
+    }
+
+    pub fn update_allowed_update_authority(
+        ctx: Context<UpdateRewarder>,
+        nft_update_authority: Pubkey,
+    ) -> ProgramResult {
+        let rewarder = &mut ctx.accounts.rewarder;
+
+        rewarder.allowed_update_authority = nft_update_authority;
+
+        Ok(())
+    }
+
+    pub fn update_collection_strategy(
+        ctx: Context<UpdateRewarder>,
+        collection_strategy: CollectionStrategy,
+    ) -> ProgramResult {
+        let rewarder = &mut ctx.accounts.rewarder;
+
+        rewarder.collection_strategy = collection_strategy;
+
+        Ok(())
+    }
+
+    pub fn update_enforce_metadata(
+        ctx: Context<UpdateRewarder>,
+        enforce_metadata: bool,
+    ) -> ProgramResult {
+        let rewarder = &mut ctx.accounts.rewarder;
+
+        rewarder.enforce_metadata = enforce_metadata;
+
+        Ok(())
+    }
+
+    pub fn update_schedule(
+        ctx: Context<UpdateRewardRate>,
+        schedule: EmissionSchedule,
+    ) -> ProgramResult {
+        let rewarder = &mut ctx.accounts.rewarder;
+        let clock = &ctx.accounts.clock;
+
+        let ends_after_start = schedule.end_time == 0 || schedule.end_time > schedule.start_time;
+        let valid_halving = schedule.halving_interval == 0
+            || (schedule.halving_interval > 0 && schedule.start_time > 0);
+        if schedule.start_time < 0 || schedule.end_time < 0 || !ends_after_start || !valid_halving {
+            return Err(StakingError::InvalidEmissionSchedule.into());
+        }
+
+        // Accrue rewards under the old schedule so the new one only applies from now on
+        update_accrued_reward(rewarder, clock.unix_timestamp)?;
+        rewarder.schedule = schedule;
+
+        Ok(())
+    }
+
+    pub fn update_emission_cap(ctx: Context<UpdateRewarder>, emission_cap: u64) -> ProgramResult {
+        let rewarder = &mut ctx.accounts.rewarder;
+
+        rewarder.emission_cap = emission_cap;
+
+        Ok(())
+    }
+
+    pub fn set_paused(ctx: Context<UpdateRewardRate>, paused: PauseFlags) -> ProgramResult {
+        let rewarder = &mut ctx.accounts.rewarder;
+        let clock = &ctx.accounts.clock;
+
+        // Accrue rewards under the old flags so pausing accrual only affects time from now on
+        update_accrued_reward(rewarder, clock.unix_timestamp)?;
+        rewarder.paused = paused;
+
+        Ok(())
+    }
+
+    pub fn update_lockup_tiers(
+        ctx: Context<UpdateRewarder>,
+        lockup_tiers: Vec<LockupTier>,
+    ) -> ProgramResult {
+        let rewarder = &mut ctx.accounts.rewarder;
+
+        let valid_tiers = lockup_tiers
+            .iter()
+            .all(|tier| tier.duration >= 0 && tier.multiplier > 0);
+        if lockup_tiers.is_empty() || lockup_tiers.len() > MAX_LOCKUP_TIERS || !valid_tiers {
+            return Err(StakingError::InvalidLockupTiers.into());
+        }
+
+        rewarder.lockup_tiers = lockup_tiers;
+
+        Ok(())
+    }
+
+    pub fn update_unbonding(
+        ctx: Context<UpdateRewarder>,
+        unbonding_period: i64,
+        early_unstake_penalty_bps: u16,
+    ) -> ProgramResult {
+        let rewarder = &mut ctx.accounts.rewarder;
+
+        if unbonding_period < 0 || early_unstake_penalty_bps as u32 > BASE_MULTIPLIER {
+            return Err(StakingError::InvalidUnbondingConfig.into());
+        }
+
+        rewarder.unbonding_period = unbonding_period;
+        rewarder.early_unstake_penalty_bps = early_unstake_penalty_bps;
+
+        Ok(())
+    }
+
+    pub fn update_weights_root(
+        ctx: Context<UpdateRewarder>,
+        weights_root: [u8; 32],
+    ) -> ProgramResult {
+        let rewarder = &mut ctx.accounts.rewarder;
+
+        rewarder.weights_root = weights_root;
+
+        Ok(())
+    }
+
+    pub fn update_allowlist_root(
+        ctx: Context<UpdateRewarder>,
+        allowlist_root: [u8; 32],
+    ) -> ProgramResult {
+        let rewarder = &mut ctx.accounts.rewarder;
+
+        rewarder.allowlist_root = allowlist_root;
+
+        Ok(())
+    }
+
+    pub fn update_metadata_policy(
+        ctx: Context<UpdateRewarder>,
+        metadata_policy: MetadataPolicy,
+        additional_update_authorities: Vec<Pubkey>,
+    ) -> ProgramResult {
+        let rewarder = &mut ctx.accounts.rewarder;
+
+        if additional_update_authorities.len() > MAX_ADDITIONAL_UPDATE_AUTHORITIES {
+            return Err(StakingError::InvalidMetadataPolicy.into());
+        }
+
+        rewarder.metadata_policy = metadata_policy;
+        rewarder.additional_update_authorities = additional_update_authorities;
+
+        Ok(())
+    }
+
+    pub fn propose_authority(ctx: Context<UpdateRewarder>, new_authority: Pubkey) -> ProgramResult {
+        let rewarder = &mut ctx.accounts.rewarder;
+
+        //proposing the default pubkey cancels a pending transfer
+        rewarder.pending_authority = new_authority;
+
+        Ok(())
+    }
+
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> ProgramResult {
+        let rewarder = &mut ctx.accounts.rewarder;
+
+        rewarder.authority = ctx.accounts.pending_authority.key();
+        rewarder.pending_authority = Pubkey::default();
+
+        Ok(())
+    }
+
+    pub fn fund_rewarder(ctx: Context<FundRewarder>, amount: u64) -> ProgramResult {
+        let rewarder = &ctx.accounts.rewarder;
+        let reward_vault = &ctx.accounts.reward_vault;
+
+        let transfer_accounts = Transfer {
+            from: ctx.accounts.funder_token_account.to_account_info(),
+            to: reward_vault.to_account_info(),
+            authority: ctx.accounts.funder.to_account_info(),
+        };
+        let transfer_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_accounts,
+        );
+        token::transfer(transfer_ctx, amount)?;
+
+        let vault_balance = reward_vault
+            .amount
+            .checked_add(amount)
+            .ok_or(StakingError::RewardOverflow)?;
+        match calculate_runway(vault_balance, rewarder.reward_rate, rewarder.total_weight) {
+            Some(runway) => msg!(
+                "Reward vault holds {} tokens, enough for {} seconds",
+                vault_balance,
+                runway
+            ),
+            None => msg!("Reward vault holds {} tokens", vault_balance),
+        }
+
+        Ok(())
+    }
+
     pub fn initialize_stake_account(
         ctx: Context<InitializeStakeAccount>,
         bump: u8,
@@ -62,223 +388,1552 @@ pub mod sol_nft_staking {
         stake_account.owner = ctx.accounts.owner.key();
         stake_account.rewarder = ctx.accounts.rewarder.key();
         stake_account.num_staked = 0;
+        stake_account.total_weight = 0;
         stake_account.bump = bump;
         stake_account.last_claimed = 0;
+        stake_account.reward_per_nft_paid = ctx.accounts.rewarder.accrued_reward_per_nft;
+        stake_account.pending_reward = 0;
+        stake_account.stream_checkpoints = Default::default();
+        stake_account.version = STAKE_ACCOUNT_VERSION;
 
         Ok(())
     }
 
-    pub fn stake_nft(ctx: Context<StakeNft>) -> ProgramResult {
+    pub fn stake_nft<'info>(
+        ctx: Context<'_, '_, '_, 'info, StakeNft<'info>>,
+        receipt_bump: u8,
+        lockup_tier: u8,
+        weight: u32,
+        weight_proof: Vec<[u8; 32]>,
+        allowlist_proof: Vec<[u8; 32]>,
+    ) -> ProgramResult {
         let owner = &ctx.accounts.owner;
         let rewarder = &mut ctx.accounts.rewarder;
         let stake_account = &mut ctx.accounts.stake_account;
+        let stake_receipt = &mut ctx.accounts.stake_receipt;
         let reward_mint = &ctx.accounts.reward_mint;
         let reward_autority = &ctx.accounts.reward_authority;
+        let reward_vault = &ctx.accounts.reward_vault;
         let reward_token_account = &ctx.accounts.reward_token_account;
         let nft_mint = &ctx.accounts.nft_mint;
         let nft_token_account = &ctx.accounts.nft_token_account;
-        // let nft_vault = &ctx.accounts.nft_vault;
+        let nft_vault = &ctx.accounts.nft_vault;
+        let nft_edition = &ctx.accounts.nft_edition;
 
         let token_program = &ctx.accounts.token_program;
         let clock = &ctx.accounts.clock;
 
+        if rewarder.paused.staking {
+            return Err(StakingError::StakingPaused.into());
+        }
+
+        if !ctx.accounts.blocked_mint.data_is_empty() {
+            return Err(StakingError::MintBlocked.into());
+        }
+
         if rewarder.enforce_metadata {
             let remaining = ctx.remaining_accounts;
             let metadata = get_metadata_account(remaining)?;
             check_metadata(&metadata, &nft_mint.key(), rewarder)?;
+            check_edition(
+                &ctx.accounts.nft_edition,
+                nft_mint.decimals,
+                nft_mint.mint_authority,
+            )?;
+        }
+
+        if rewarder.allowlist_root != [0; 32] {
+            let leaf = merkle::mint_leaf(&nft_mint.key());
+            if !merkle::verify_proof(&allowlist_proof, &rewarder.allowlist_root, leaf) {
+                return Err(StakingError::InvalidAllowlistProof.into());
+            }
         }
 
         // Calculate and claim any pending rewards
+        update_accrued_reward(rewarder, clock.unix_timestamp)?;
         let to_reward = calculate_reward(
-            rewarder.reward_rate,
-            stake_account.num_staked,
-            stake_account.last_claimed,
-            clock.unix_timestamp,
-        );
-
-        transfer_reward(
+            rewarder.accrued_reward_per_nft,
+            stake_account.reward_per_nft_paid,
+            stake_account.total_weight,
+        )?
+        .checked_add(stake_account.pending_reward)
+        .ok_or(StakingError::RewardOverflow)?;
+
+        let rewarded = transfer_reward(
             to_reward,
             rewarder,
+            stake_account,
             reward_mint,
+            reward_vault,
             reward_token_account,
             reward_autority,
             token_program,
         )?;
+        stake_account.pending_reward = to_reward - rewarded;
         stake_account.last_claimed = clock.unix_timestamp;
+        stake_account.reward_per_nft_paid = rewarder.accrued_reward_per_nft;
+        settle_reward_streams(
+            rewarder,
+            stake_account,
+            ctx.remaining_accounts,
+            token_program,
+            None,
+        )?;
+
+        let tier = *rewarder
+            .lockup_tiers
+            .get(lockup_tier as usize)
+            .ok_or(StakingError::InvalidLockupTier)?;
+
+        //without a weights root every nft earns at 1x
+        let nft_weight = if rewarder.weights_root == [0; 32] {
+            BASE_MULTIPLIER
+        } else {
+            let leaf = merkle::weight_leaf(&nft_mint.key(), weight);
+            if !merkle::verify_proof(&weight_proof, &rewarder.weights_root, leaf) {
+                return Err(StakingError::InvalidWeightProof.into());
+            }
+            weight
+        };
+        let staked_weight = calculate_weight(nft_weight, tier.multiplier);
 
         //increase the number of staked nfts by 1
-        stake_account.num_staked += 1;
-        rewarder.total_staked += 1;
-
-        //transfer nft ownership to vault
-        let authority_accounts = SetAuthority {
-            current_authority: owner.to_account_info(),
-            account_or_mint: nft_token_account.to_account_info(),
-        };
-        let authority_ctx = CpiContext::new(token_program.to_account_info(), authority_accounts);
-        token::set_authority(
-            authority_ctx,
-            AuthorityType::AccountOwner,
-            Some(stake_account.key()),
-        )?;
+        stake_account.num_staked = stake_account
+            .num_staked
+            .checked_add(1)
+            .ok_or(StakingError::ArithmeticOverflow)?;
+        rewarder.total_staked = rewarder
+            .total_staked
+            .checked_add(1)
+            .ok_or(StakingError::ArithmeticOverflow)?;
+
+        //the nft earns rewards weighted by its weight and lockup multiplier
+        stake_account.total_weight = stake_account
+            .total_weight
+            .checked_add(staked_weight)
+            .ok_or(StakingError::ArithmeticOverflow)?;
+        rewarder.total_weight = rewarder
+            .total_weight
+            .checked_add(staked_weight)
+            .ok_or(StakingError::ArithmeticOverflow)?;
+
+        stake_receipt.stake_account = stake_account.key();
+        stake_receipt.mint = nft_mint.key();
+        stake_receipt.staked_at = clock.unix_timestamp;
+        stake_receipt.multiplier = tier.multiplier;
+        stake_receipt.weight = staked_weight;
+        stake_receipt.lockup_end = clock
+            .unix_timestamp
+            .checked_add(tier.duration)
+            .ok_or(StakingError::ArithmeticOverflow)?;
+        stake_receipt.unbonding_end = 0;
+        stake_receipt.bump = receipt_bump;
+        stake_receipt.reward_per_nft_staked = rewarder.accrued_reward_per_nft;
+        for (staked, stream) in stake_receipt
+            .stream_rewards_per_nft_staked
+            .iter_mut()
+            .zip(rewarder.reward_streams.iter())
+        {
+            *staked = stream.accrued_reward_per_nft;
+        }
+
+        match rewarder.custody_mode {
+            CustodyMode::TransferAuthority => {
+                //transfer nft ownership to vault
+                let authority_accounts = SetAuthority {
+                    current_authority: owner.to_account_info(),
+                    account_or_mint: nft_token_account.to_account_info(),
+                };
+                let authority_ctx =
+                    CpiContext::new(token_program.to_account_info(), authority_accounts);
+                token::set_authority(
+                    authority_ctx,
+                    AuthorityType::AccountOwner,
+                    Some(stake_account.key()),
+                )?;
+            }
+            CustodyMode::Escrow => {
+                if nft_vault.data_is_empty() {
+                    let create_accounts = associated_token::Create {
+                        payer: owner.to_account_info(),
+                        associated_token: nft_vault.to_account_info(),
+                        authority: stake_account.to_account_info(),
+                        mint: nft_mint.to_account_info(),
+                        system_program: ctx.accounts.system_program.to_account_info(),
+                        token_program: token_program.to_account_info(),
+                        rent: ctx.accounts.rent.to_account_info(),
+                    };
+                    let create_ctx = CpiContext::new(
+                        ctx.accounts.associated_token_program.to_account_info(),
+                        create_accounts,
+                    );
+                    associated_token::create(create_ctx)?;
+                }
+
+                //move the nft into the stake account's vault
+                let transfer_accounts = Transfer {
+                    from: nft_token_account.to_account_info(),
+                    to: nft_vault.to_account_info(),
+                    authority: owner.to_account_info(),
+                };
+                let transfer_ctx =
+                    CpiContext::new(token_program.to_account_info(), transfer_accounts);
+                token::transfer(transfer_ctx, 1)?;
+            }
+            CustodyMode::Freeze => {
+                //delegate the nft to the stake account so it can freeze it in place
+                let approve_accounts = Approve {
+                    to: nft_token_account.to_account_info(),
+                    delegate: stake_account.to_account_info(),
+                    authority: owner.to_account_info(),
+                };
+                let approve_ctx =
+                    CpiContext::new(token_program.to_account_info(), approve_accounts);
+                token::approve(approve_ctx, 1)?;
+
+                let collection_seed = get_collection_seed(&rewarder.collection);
+
+                let stake_account_seeds = &[
+                    collection_seed.as_slice(),
+                    &id().to_bytes(),
+                    ACCOUNT_PREFIX,
+                    &rewarder.key().to_bytes(),
+                    &owner.key().to_bytes(),
+                    &[stake_account.bump],
+                ];
+                let freeze_ix = anchor_metaplex::freeze_delegated_account(
+                    stake_account.key(),
+                    nft_token_account.key(),
+                    nft_edition.key(),
+                    nft_mint.key(),
+                );
+                invoke_signed(
+                    &freeze_ix,
+                    &[
+                        stake_account.to_account_info(),
+                        nft_token_account.to_account_info(),
+                        nft_edition.to_account_info(),
+                        nft_mint.to_account_info(),
+                        token_program.to_account_info(),
+                        ctx.accounts.token_metadata_program.to_account_info(),
+                    ],
+                    &[&stake_account_seeds[..]],
+                )?;
+            }
+        }
 
         Ok(())
     }
 
-    pub fn unstake_nft(ctx: Context<UnstakeNft>) -> ProgramResult {
+    pub fn unstake_nft<'info>(ctx: Context<'_, '_, '_, 'info, UnstakeNft<'info>>) -> ProgramResult {
         let owner = &ctx.accounts.owner;
         let rewarder = &mut ctx.accounts.rewarder;
         let stake_account = &mut ctx.accounts.stake_account;
+        let mut stake_receipt: Account<StakeReceipt> =
+            Account::try_from(&ctx.accounts.stake_receipt)?;
         let reward_mint = &ctx.accounts.reward_mint;
         let reward_autority = &ctx.accounts.reward_authority;
+        let reward_vault = &ctx.accounts.reward_vault;
         let reward_token_account = &ctx.accounts.reward_token_account;
+        let nft_mint = &ctx.accounts.nft_mint;
         let nft_token_account = &ctx.accounts.nft_token_account;
-        // let nft_vault = &ctx.accounts.nft_vault;
+        let nft_vault = &ctx.accounts.nft_vault;
+        let nft_edition = &ctx.accounts.nft_edition;
 
         let token_program = &ctx.accounts.token_program;
         let clock = &ctx.accounts.clock;
 
-        // Calculate and claim any pending rewards
+        if stake_receipt.stake_account != stake_account.key()
+            || stake_receipt.mint != nft_mint.key()
+        {
+            return Err(StakingError::InvalidStakeReceipt.into());
+        }
+        if stake_receipt.blocked {
+            return Err(StakingError::MintBlocked.into());
+        }
 
-        let to_reward = calculate_reward(
-            rewarder.reward_rate,
-            stake_account.num_staked,
-            stake_account.last_claimed,
-            clock.unix_timestamp,
-        );
+        //the nft stops earning when it is unstaked and is withdrawn once unbonded
+        let unbonding = stake_receipt.unbonding_end != 0;
+        let mut penalty_bps = 0;
+        if !unbonding && clock.unix_timestamp < stake_receipt.lockup_end {
+            return Err(StakingError::StakeLocked.into());
+        }
+        if unbonding && clock.unix_timestamp < stake_receipt.unbonding_end {
+            if rewarder.early_unstake_penalty_bps == 0 {
+                return Err(StakingError::StillUnbonding.into());
+            }
+            penalty_bps = rewarder.early_unstake_penalty_bps;
+        }
+
+        //an unbonding nft's rewards are held in its receipt, and withdrawing it early
+        //forfeits a share of what it earned since it was staked
+        let unstake = if unbonding {
+            Some(Unstake::Withdraw { penalty_bps })
+        } else if rewarder.unbonding_period > 0 {
+            Some(Unstake::StartUnbonding {
+                weight: stake_receipt.weight,
+            })
+        } else {
+            None
+        };
+
+        // Calculate and claim any pending rewards
+        update_accrued_reward(rewarder, clock.unix_timestamp)?;
+        let earned = calculate_reward(
+            rewarder.accrued_reward_per_nft,
+            stake_account.reward_per_nft_paid,
+            stake_account.total_weight,
+        )?
+        .checked_add(stake_account.pending_reward)
+        .ok_or(StakingError::RewardOverflow)?;
+        let receipt: &mut StakeReceipt = &mut stake_receipt;
+        let to_reward = match unstake {
+            Some(unstake) => settle_unstaked_reward(
+                unstake,
+                earned,
+                rewarder.accrued_reward_per_nft,
+                stake_account.reward_per_nft_paid,
+                receipt.reward_per_nft_staked,
+                &mut receipt.unbonding_reward,
+                &mut receipt.lifetime_reward,
+            )?,
+            None => earned,
+        };
 
-        transfer_reward(
+        let rewarded = transfer_reward(
             to_reward,
             rewarder,
+            stake_account,
             reward_mint,
+            reward_vault,
             reward_token_account,
             reward_autority,
             token_program,
         )?;
+        stake_account.pending_reward = to_reward - rewarded;
         stake_account.last_claimed = clock.unix_timestamp;
+        stake_account.reward_per_nft_paid = rewarder.accrued_reward_per_nft;
+        settle_reward_streams(
+            rewarder,
+            stake_account,
+            ctx.remaining_accounts,
+            token_program,
+            unstake.map(|unstake| (unstake, receipt)),
+        )?;
+
+        if !unbonding {
+            remove_staked_nft(rewarder, stake_account, stake_receipt.weight);
+
+            //with a cooldown the nft stays in custody until it is withdrawn
+            if rewarder.unbonding_period > 0 {
+                stake_receipt.unbonding_end = clock
+                    .unix_timestamp
+                    .checked_add(rewarder.unbonding_period)
+                    .ok_or(StakingError::ArithmeticOverflow)?;
+                msg!("NFT can be withdrawn at {}", stake_receipt.unbonding_end);
+                return stake_receipt.exit(ctx.program_id);
+            }
+        }
 
-        //descrease the number of staked nfts by 1
-        stake_account.num_staked = stake_account.num_staked.checked_sub(1).unwrap_or(0);
-        rewarder.total_staked = rewarder.total_staked.checked_sub(1).unwrap_or(0);
+        release_nft(
+            rewarder,
+            stake_account,
+            owner,
+            nft_mint,
+            nft_token_account,
+            nft_vault,
+            nft_edition,
+            token_program,
+            &ctx.accounts.token_metadata_program,
+            true,
+        )?;
 
-        let stake_account_seeds = &[
-            rewarder.collection.as_bytes(),
-            &id().to_bytes(),
-            ACCOUNT_PREFIX,
-            &rewarder.key().to_bytes(),
-            &owner.key().to_bytes(),
-            &[stake_account.bump],
-        ];
+        //the nft has been withdrawn so close the receipt and refund its rent
+        stake_receipt.close(owner.to_account_info())
+    }
 
-        let stake_account_signer = &[&stake_account_seeds[..]];
+    pub fn block_mint<'info>(
+        ctx: Context<'_, '_, '_, 'info, BlockMint<'info>>,
+        bump: u8,
+    ) -> ProgramResult {
+        let rewarder = &mut ctx.accounts.rewarder;
+        let blocked_mint = &mut ctx.accounts.blocked_mint;
+        let nft_mint = &ctx.accounts.nft_mint;
+        let clock = &ctx.accounts.clock;
 
-        //transfer nft to vault
-        let authority_accounts = SetAuthority {
-            current_authority: stake_account.to_account_info(),
-            account_or_mint: nft_token_account.to_account_info(),
+        blocked_mint.rewarder = rewarder.key();
+        blocked_mint.mint = nft_mint.key();
+        blocked_mint.blocked_at = clock.unix_timestamp;
+        blocked_mint.stake_account = Pubkey::default();
+        blocked_mint.bump = bump;
+
+        //a staked nft has to be blocked together with its stake account and receipt
+        //so that it stops earning, otherwise it is only kept from being staked again
+        let (stake_account_info, stake_receipt_info) = match ctx.remaining_accounts {
+            [] => return Ok(()),
+            [stake_account, stake_receipt] => (stake_account, stake_receipt),
+            _ => return Err(StakingError::InvalidStakeReceipt.into()),
         };
-        let authority_ctx = CpiContext::new_with_signer(
-            token_program.to_account_info(),
-            authority_accounts,
-            stake_account_signer,
-        );
-        token::set_authority(
-            authority_ctx,
-            AuthorityType::AccountOwner,
-            Some(owner.key()),
-        )?;
+        let mut stake_account: Account<NftStakeAccount> = Account::try_from(stake_account_info)?;
+        let mut stake_receipt: Account<StakeReceipt> = Account::try_from(stake_receipt_info)?;
+        check_stake_receipt(rewarder, &stake_account, &stake_receipt, &nft_mint.key())?;
+
+        settle_pending_reward(rewarder, &mut stake_account, clock.unix_timestamp)?;
+        //unbonding nfts have already stopped earning
+        if stake_receipt.unbonding_end == 0 {
+            stake_account.total_weight = stake_account
+                .total_weight
+                .saturating_sub(stake_receipt.weight);
+            rewarder.total_weight = rewarder.total_weight.saturating_sub(stake_receipt.weight);
+        }
+        stake_receipt.blocked = true;
+        stake_account.num_blocked = stake_account
+            .num_blocked
+            .checked_add(1)
+            .ok_or(StakingError::ArithmeticOverflow)?;
+        blocked_mint.stake_account = stake_account.key();
+
+        stake_account.exit(ctx.program_id)?;
+        stake_receipt.exit(ctx.program_id)
+    }
 
-        Ok(())
+    pub fn unblock_mint<'info>(
+        ctx: Context<'_, '_, '_, 'info, UnblockMint<'info>>,
+    ) -> ProgramResult {
+        let rewarder = &mut ctx.accounts.rewarder;
+        let blocked_mint = &ctx.accounts.blocked_mint;
+        let clock = &ctx.accounts.clock;
+
+        if blocked_mint.stake_account == Pubkey::default() {
+            return Ok(());
+        }
+
+        //the nft is still staked, so it has to be passed with its stake account and
+        //receipt to start earning again
+        let (stake_account_info, stake_receipt_info) = match ctx.remaining_accounts {
+            [stake_account, stake_receipt] => (stake_account, stake_receipt),
+            _ => return Err(StakingError::InvalidStakeReceipt.into()),
+        };
+        let mut stake_account: Account<NftStakeAccount> = Account::try_from(stake_account_info)?;
+        let mut stake_receipt: Account<StakeReceipt> = Account::try_from(stake_receipt_info)?;
+        if stake_account.key() != blocked_mint.stake_account {
+            return Err(StakingError::InvalidStakeReceipt.into());
+        }
+        check_stake_receipt(rewarder, &stake_account, &stake_receipt, &blocked_mint.mint)?;
+
+        settle_pending_reward(rewarder, &mut stake_account, clock.unix_timestamp)?;
+        if stake_receipt.unbonding_end == 0 {
+            stake_account.total_weight = stake_account
+                .total_weight
+                .checked_add(stake_receipt.weight)
+                .ok_or(StakingError::ArithmeticOverflow)?;
+            rewarder.total_weight = rewarder
+                .total_weight
+                .checked_add(stake_receipt.weight)
+                .ok_or(StakingError::ArithmeticOverflow)?;
+        }
+        stake_receipt.blocked = false;
+        stake_account.num_blocked = stake_account.num_blocked.saturating_sub(1);
+
+        stake_account.exit(ctx.program_id)?;
+        stake_receipt.exit(ctx.program_id)
     }
 
-    pub fn claim(ctx: Context<Claim>) -> ProgramResult {
-        let rewarder = &ctx.accounts.rewarder;
+    pub fn force_unstake(ctx: Context<ForceUnstake>) -> ProgramResult {
+        let owner = &ctx.accounts.owner;
+        let rewarder = &mut ctx.accounts.rewarder;
         let stake_account = &mut ctx.accounts.stake_account;
-        let reward_mint = &ctx.accounts.reward_mint;
-        let reward_autority = &ctx.accounts.reward_authority;
-        let reward_token_account = &ctx.accounts.reward_account;
-
-        let token_program = &ctx.accounts.token_program;
+        let stake_receipt: Account<StakeReceipt> = Account::try_from(&ctx.accounts.stake_receipt)?;
+        let nft_mint = &ctx.accounts.nft_mint;
         let clock = &ctx.accounts.clock;
 
-        let to_reward = calculate_reward(
-            rewarder.reward_rate,
-            stake_account.num_staked,
-            stake_account.last_claimed,
-            clock.unix_timestamp,
-        );
+        if stake_receipt.stake_account != stake_account.key()
+            || stake_receipt.mint != nft_mint.key()
+        {
+            return Err(StakingError::InvalidStakeReceipt.into());
+        }
+        if ctx.accounts.blocked_mint.data_is_empty() {
+            return Err(StakingError::MintNotBlocked.into());
+        }
+        let mut blocked_mint: Account<BlockedMint> = Account::try_from(&ctx.accounts.blocked_mint)?;
+
+        //settle the rewards earned so far without paying them out. The owner can
+        //still claim them but the blocked nft earns nothing from now on
+        settle_pending_reward(rewarder, stake_account, clock.unix_timestamp)?;
+
+        //rewards held while the nft was unbonding are released without a penalty
+        stake_account.pending_reward = stake_account
+            .pending_reward
+            .checked_add(stake_receipt.unbonding_reward)
+            .ok_or(StakingError::RewardOverflow)?;
+        for (checkpoint, held) in stake_account
+            .stream_checkpoints
+            .iter_mut()
+            .zip(stake_receipt.unbonding_stream_rewards)
+        {
+            checkpoint.pending_reward = checkpoint
+                .pending_reward
+                .checked_add(held)
+                .ok_or(StakingError::RewardOverflow)?;
+        }
 
-        transfer_reward(
-            to_reward,
+        //unbonding nfts have already stopped earning, and so have nfts that were
+        //staked when they were blocked
+        if stake_receipt.unbonding_end == 0 {
+            let weight = if stake_receipt.blocked {
+                0
+            } else {
+                stake_receipt.weight
+            };
+            remove_staked_nft(rewarder, stake_account, weight);
+        }
+        if stake_receipt.blocked {
+            stake_account.num_blocked = stake_account.num_blocked.saturating_sub(1);
+        }
+        blocked_mint.stake_account = Pubkey::default();
+        blocked_mint.exit(ctx.program_id)?;
+
+        release_nft(
             rewarder,
-            reward_mint,
-            reward_token_account,
-            reward_autority,
-            token_program,
+            stake_account,
+            owner,
+            nft_mint,
+            &ctx.accounts.nft_token_account,
+            &ctx.accounts.nft_vault,
+            &ctx.accounts.nft_edition,
+            &ctx.accounts.token_program,
+            &ctx.accounts.token_metadata_program,
+            false,
         )?;
-        stake_account.last_claimed = clock.unix_timestamp;
 
-        Ok(())
+        stake_receipt.close(owner.to_account_info())
     }
-}
 
-pub fn calculate_reward(
-    reward_rate: u64,
-    num_staked: u16,
-    last_claimed: i64,
-    current_time: i64,
-) -> u64 {
-    if num_staked == 0 {
-        return 0;
-    }
+    pub fn migrate_rewarder(ctx: Context<MigrateRewarder>) -> ProgramResult {
+        let rewarder_info = &ctx.accounts.rewarder;
+        let data = rewarder_info.try_borrow_data()?;
+        //v0 rewarders end before the fields added since, so they don't read as the current layout
+        if NftStakeRewarder::try_deserialize(&mut &data[..]).is_ok() {
+            return Err(StakingError::AlreadyMigrated.into());
+        }
+        let rewarder = upgrade_v0_rewarder(&data, ctx.accounts.clock.unix_timestamp)?;
+        drop(data);
+
+        let new_len =
+            NftStakeRewarder::calculate_len(rewarder.creators.len(), &rewarder.collection);
+        grow_account(
+            rewarder_info,
+            &ctx.accounts.payer,
+            &ctx.accounts.system_program.to_account_info(),
+            &ctx.accounts.rent,
+            new_len,
+        )?;
 
-    let elapsed_time = current_time - last_claimed;
+        let mut data = rewarder_info.try_borrow_mut_data()?;
+        rewarder.try_serialize(&mut &mut data[..])?;
 
-    if elapsed_time <= 0 {
-        return 0;
+        Ok(())
     }
 
-    reward_rate * elapsed_time as u64 * num_staked as u64
-}
+    pub fn migrate_stake_account(ctx: Context<MigrateStakeAccount>) -> ProgramResult {
+        let rewarder = &ctx.accounts.rewarder;
+        let stake_account_info = &ctx.accounts.stake_account;
+        let data = stake_account_info.try_borrow_data()?;
+        if NftStakeAccount::try_deserialize(&mut &data[..]).is_ok() {
+            return Err(StakingError::AlreadyMigrated.into());
+        }
+        let stake_account = upgrade_v0_stake_account(&data, rewarder)?;
+        drop(data);
 
+        if stake_account.rewarder != rewarder.key() {
+            return Err(StakingError::InvalidRewarder.into());
+        }
+
+        grow_account(
+            stake_account_info,
+            &ctx.accounts.payer,
+            &ctx.accounts.system_program.to_account_info(),
+            &ctx.accounts.rent,
+            NftStakeAccount::LEN,
+        )?;
+
+        let mut data = stake_account_info.try_borrow_mut_data()?;
+        stake_account.try_serialize(&mut &mut data[..])?;
+
+        Ok(())
+    }
+
+    pub fn migrate_staked_nft(ctx: Context<MigrateStakedNft>, receipt_bump: u8) -> ProgramResult {
+        let stake_account = &mut ctx.accounts.stake_account;
+        let stake_receipt = &mut ctx.accounts.stake_receipt;
+        let clock = &ctx.accounts.clock;
+
+        //v0 nfts are already counted in the stake account's weight, they only need a receipt
+        stake_account.v0_staked = stake_account
+            .v0_staked
+            .checked_sub(1)
+            .ok_or(StakingError::NoV0StakedNfts)?;
+
+        //v0 didn't record when nfts were staked and had no lockups
+        stake_receipt.stake_account = stake_account.key();
+        stake_receipt.mint = ctx.accounts.nft_mint.key();
+        stake_receipt.staked_at = clock.unix_timestamp;
+        stake_receipt.multiplier = BASE_MULTIPLIER;
+        stake_receipt.weight = BASE_MULTIPLIER as u64;
+        stake_receipt.lockup_end = clock.unix_timestamp;
+        stake_receipt.unbonding_end = 0;
+        stake_receipt.bump = receipt_bump;
+        //nor what they had earned, so they count as staked from the last payout
+        stake_receipt.reward_per_nft_staked = stake_account.reward_per_nft_paid;
+        for (staked, checkpoint) in stake_receipt
+            .stream_rewards_per_nft_staked
+            .iter_mut()
+            .zip(stake_account.stream_checkpoints.iter())
+        {
+            *staked = checkpoint.reward_per_nft_paid;
+        }
+
+        Ok(())
+    }
+
+    pub fn claim<'info>(ctx: Context<'_, '_, '_, 'info, Claim<'info>>) -> ProgramResult {
+        let rewarder = &mut ctx.accounts.rewarder;
+        let stake_account = &mut ctx.accounts.stake_account;
+        let reward_mint = &ctx.accounts.reward_mint;
+        let reward_autority = &ctx.accounts.reward_authority;
+        let reward_vault = &ctx.accounts.reward_vault;
+        let reward_token_account = &ctx.accounts.reward_account;
+
+        let token_program = &ctx.accounts.token_program;
+        let clock = &ctx.accounts.clock;
+
+        if rewarder.paused.claiming {
+            return Err(StakingError::ClaimingPaused.into());
+        }
+        if stake_account.num_blocked > 0 {
+            return Err(StakingError::MintBlocked.into());
+        }
+        //the cap only covers the reward mint, so streams can still be claimed
+        let capped_out =
+            rewarder.emission_cap > 0 && rewarder.total_emitted >= rewarder.emission_cap;
+
+        update_accrued_reward(rewarder, clock.unix_timestamp)?;
+        let to_reward = calculate_reward(
+            rewarder.accrued_reward_per_nft,
+            stake_account.reward_per_nft_paid,
+            stake_account.total_weight,
+        )?
+        .checked_add(stake_account.pending_reward)
+        .ok_or(StakingError::RewardOverflow)?;
+
+        let rewarded = transfer_reward(
+            to_reward,
+            rewarder,
+            stake_account,
+            reward_mint,
+            reward_vault,
+            reward_token_account,
+            reward_autority,
+            token_program,
+        )?;
+        stake_account.pending_reward = to_reward - rewarded;
+        stake_account.last_claimed = clock.unix_timestamp;
+        stake_account.reward_per_nft_paid = rewarder.accrued_reward_per_nft;
+        let streams_owed = settle_reward_streams(
+            rewarder,
+            stake_account,
+            ctx.remaining_accounts,
+            token_program,
+            None,
+        )?;
+
+        if capped_out && !streams_owed {
+            return Err(StakingError::EmissionCapReached.into());
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads a rewarder written with the v0 layout and returns it in the current
+/// layout, set up the way `initialize_rewarder` sets up new rewarders. v0 stake
+/// accounts earned `reward_rate` per NFT for every second since they last
+/// claimed, so the accumulator starts out as if it had been accruing at that
+/// rate since time 0 and `upgrade_v0_stake_account` checkpoints each stake
+/// account at the time it last claimed.
+pub fn upgrade_v0_rewarder(
+    data: &[u8],
+    current_time: i64,
+) -> std::result::Result<NftStakeRewarder, ProgramError> {
+    if !data.starts_with(&<NftStakeRewarder as anchor_lang::Discriminator>::discriminator()) {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let v0 = NftStakeRewarderV0::deserialize(&mut &data[8..])?;
+
+    let accrued_reward_per_nft = (v0.reward_rate as u128)
+        .checked_mul(current_time as u128)
+        .ok_or(StakingError::RewardOverflow)?;
+
+    Ok(NftStakeRewarder {
+        authority: v0.authority,
+        reward_mint: v0.reward_mint,
+        reward_authority_bump: v0.reward_authority_bump,
+        reward_rate: v0.reward_rate,
+        allowed_update_authority: v0.allowed_update_authority,
+        creators: v0.creators,
+        collection: v0.collection,
+        enforce_metadata: v0.enforce_metadata,
+        total_staked: v0.total_staked,
+        accrued_reward_per_nft,
+        last_accrued: current_time,
+        //v0 minted rewards and took ownership of the nft token accounts
+        reward_mode: RewardMode::Mint,
+        custody_mode: CustodyMode::TransferAuthority,
+        total_weight: v0.total_staked as u64 * BASE_MULTIPLIER as u64,
+        lockup_tiers: vec![LockupTier {
+            duration: 0,
+            multiplier: BASE_MULTIPLIER,
+        }],
+        collection_strategy: CollectionStrategy::NamePrefix,
+        metadata_policy: MetadataPolicy::default(),
+        version: REWARDER_VERSION,
+        v0_reward_rate: v0.reward_rate,
+        ..Default::default()
+    })
+}
+
+/// Reads a stake account written with the v0 layout and returns it in the
+/// current layout. `rewarder` must already be migrated, see `upgrade_v0_rewarder`.
+pub fn upgrade_v0_stake_account(
+    data: &[u8],
+    rewarder: &NftStakeRewarder,
+) -> std::result::Result<NftStakeAccount, ProgramError> {
+    if !data.starts_with(&<NftStakeAccount as anchor_lang::Discriminator>::discriminator()) {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let v0 = NftStakeAccountV0::deserialize(&mut &data[8..])?;
+
+    //what the stake account earned since it last claimed is what the accumulator
+    //has grown by since then at the v0 rate
+    let reward_per_nft_paid = (rewarder.v0_reward_rate as u128)
+        .checked_mul(v0.last_claimed as u128)
+        .ok_or(StakingError::RewardOverflow)?;
+
+    Ok(NftStakeAccount {
+        owner: v0.owner,
+        rewarder: v0.rewarder,
+        num_staked: v0.num_staked,
+        total_weight: v0.num_staked as u64 * BASE_MULTIPLIER as u64,
+        bump: v0.bump,
+        last_claimed: v0.last_claimed,
+        reward_per_nft_paid,
+        pending_reward: 0,
+        stream_checkpoints: [StreamCheckpoint::default(); MAX_REWARD_STREAMS],
+        version: STAKE_ACCOUNT_VERSION,
+        v0_staked: v0.num_staked,
+        num_blocked: 0,
+        reserved: Default::default(),
+    })
+}
+
+/// Brings the rewarder's reward per NFT accumulators up to `current_time` using
+/// the current reward rates and emission schedule. Nothing accrues while accrual
+/// is paused.
+pub fn update_accrued_reward(rewarder: &mut NftStakeRewarder, current_time: i64) -> ProgramResult {
+    let elapsed_time = current_time
+        .checked_sub(rewarder.last_accrued)
+        .ok_or(StakingError::ArithmeticOverflow)?;
+
+    if elapsed_time <= 0 {
+        return Ok(());
+    }
+
+    //paused time earns nothing
+    if rewarder.paused.accrual {
+        rewarder.last_accrued = current_time;
+        return Ok(());
+    }
+
+    let accrued = calculate_emission(
+        rewarder.reward_rate,
+        &rewarder.schedule,
+        rewarder.last_accrued,
+        current_time,
+    )?;
+    rewarder.accrued_reward_per_nft = rewarder
+        .accrued_reward_per_nft
+        .checked_add(accrued)
+        .ok_or(StakingError::RewardOverflow)?;
+
+    let schedule = rewarder.schedule;
+    for stream in rewarder.reward_streams.iter_mut() {
+        let accrued = calculate_emission(
+            stream.reward_rate,
+            &schedule,
+            rewarder.last_accrued,
+            current_time,
+        )?;
+        stream.accrued_reward_per_nft = stream
+            .accrued_reward_per_nft
+            .checked_add(accrued)
+            .ok_or(StakingError::RewardOverflow)?;
+    }
+    rewarder.last_accrued = current_time;
+
+    Ok(())
+}
+
+/// Returns the reward earned by a single NFT at 1x weight between `from` and `to`
+/// under the emission schedule. Time outside of the schedule earns nothing and
+/// each halving interval is integrated at its own rate.
+pub fn calculate_emission(
+    reward_rate: u64,
+    schedule: &EmissionSchedule,
+    from: i64,
+    to: i64,
+) -> std::result::Result<u128, ProgramError> {
+    let mut from = if schedule.start_time > 0 {
+        from.max(schedule.start_time)
+    } else {
+        from
+    };
+    let to = if schedule.end_time > 0 {
+        to.min(schedule.end_time)
+    } else {
+        to
+    };
+
+    let mut emitted: u128 = 0;
+    while from < to {
+        let (rate, segment_end) = if schedule.halving_interval > 0 {
+            let halvings = (from - schedule.start_time) / schedule.halving_interval;
+            let segment_end = halvings
+                .saturating_add(1)
+                .saturating_mul(schedule.halving_interval)
+                .saturating_add(schedule.start_time);
+            (
+                reward_rate.checked_shr(halvings as u32).unwrap_or(0),
+                segment_end.min(to),
+            )
+        } else {
+            (reward_rate, to)
+        };
+        //the rate has halved down to nothing
+        if rate == 0 {
+            break;
+        }
+
+        let segment = (rate as u128)
+            .checked_mul((segment_end - from) as u128)
+            .ok_or(StakingError::RewardOverflow)?;
+        emitted = emitted
+            .checked_add(segment)
+            .ok_or(StakingError::RewardOverflow)?;
+        from = segment_end;
+    }
+
+    Ok(emitted)
+}
+
+/// Removes a staked NFT and its reward weight from the stake account and rewarder totals.
+pub fn remove_staked_nft(
+    rewarder: &mut NftStakeRewarder,
+    stake_account: &mut NftStakeAccount,
+    weight: u64,
+) {
+    //descrease the number of staked nfts by 1
+    stake_account.num_staked = stake_account.num_staked.saturating_sub(1);
+    rewarder.total_staked = rewarder.total_staked.saturating_sub(1);
+    stake_account.total_weight = stake_account.total_weight.saturating_sub(weight);
+    rewarder.total_weight = rewarder.total_weight.saturating_sub(weight);
+}
+
+/// Settles what a stake account has earned into its pending rewards, in the
+/// reward mint and every reward stream, without paying anything out.
+pub fn settle_pending_reward(
+    rewarder: &mut NftStakeRewarder,
+    stake_account: &mut NftStakeAccount,
+    current_time: i64,
+) -> ProgramResult {
+    update_accrued_reward(rewarder, current_time)?;
+    stake_account.pending_reward = calculate_reward(
+        rewarder.accrued_reward_per_nft,
+        stake_account.reward_per_nft_paid,
+        stake_account.total_weight,
+    )?
+    .checked_add(stake_account.pending_reward)
+    .ok_or(StakingError::RewardOverflow)?;
+    stake_account.reward_per_nft_paid = rewarder.accrued_reward_per_nft;
+
+    for (stream, checkpoint) in rewarder
+        .reward_streams
+        .iter()
+        .zip(stake_account.stream_checkpoints.iter_mut())
+    {
+        checkpoint.pending_reward = calculate_reward(
+            stream.accrued_reward_per_nft,
+            checkpoint.reward_per_nft_paid,
+            stake_account.total_weight,
+        )?
+        .checked_add(checkpoint.pending_reward)
+        .ok_or(StakingError::RewardOverflow)?;
+        checkpoint.reward_per_nft_paid = stream.accrued_reward_per_nft;
+    }
+
+    Ok(())
+}
+
+/// Checks that `stake_receipt` is the receipt of `mint` staked with
+/// `stake_account` under `rewarder`.
+pub fn check_stake_receipt(
+    rewarder: &Account<NftStakeRewarder>,
+    stake_account: &Account<NftStakeAccount>,
+    stake_receipt: &StakeReceipt,
+    mint: &Pubkey,
+) -> ProgramResult {
+    if stake_account.rewarder != rewarder.key() {
+        return Err(StakingError::InvalidRewarder.into());
+    }
+    if stake_receipt.stake_account != stake_account.key() || stake_receipt.mint != *mint {
+        return Err(StakingError::InvalidStakeReceipt.into());
+    }
+
+    Ok(())
+}
+
+/// Returns the seed that a collection's PDAs are derived from. Seeds can be at
+/// most 32 bytes, so longer collection names are hashed. Shorter names are used
+/// as is so that existing rewarders keep their addresses.
+pub fn get_collection_seed(collection: &str) -> Vec<u8> {
+    if collection.len() > MAX_SEED_LEN {
+        hash(collection.as_bytes()).to_bytes().to_vec()
+    } else {
+        collection.as_bytes().to_vec()
+    }
+}
+
+/// Returns the rewarder seed for a pool of a collection. Pool 0 has an empty seed
+/// so that it keeps the address rewarders had before pools were introduced.
+pub fn get_pool_seed(pool_id: u64) -> Vec<u8> {
+    if pool_id == 0 {
+        vec![]
+    } else {
+        pool_id.to_le_bytes().to_vec()
+    }
+}
+
+/// Returns the address of the PDA that blocks a mint from being staked with a rewarder.
+pub fn get_blocked_mint_address(collection: &str, rewarder: &Pubkey, mint: &Pubkey) -> Pubkey {
+    let (address, _) = Pubkey::find_program_address(
+        &[
+            get_collection_seed(collection).as_slice(),
+            &id().to_bytes(),
+            BLOCKED_PREFIX,
+            &rewarder.to_bytes(),
+            &mint.to_bytes(),
+        ],
+        &id(),
+    );
+    address
+}
+
+/// Hands a staked NFT back to its owner according to the rewarder's custody mode.
+#[allow(clippy::too_many_arguments)]
+pub fn release_nft<'info>(
+    rewarder: &Account<'info, NftStakeRewarder>,
+    stake_account: &Account<'info, NftStakeAccount>,
+    owner: &AccountInfo<'info>,
+    nft_mint: &Account<'info, Mint>,
+    nft_token_account: &Account<'info, TokenAccount>,
+    nft_vault: &AccountInfo<'info>,
+    nft_edition: &AccountInfo<'info>,
+    token_program: &Program<'info, Token>,
+    token_metadata_program: &Program<'info, MetaplexTokenMetadata>,
+    owner_signed: bool,
+) -> ProgramResult {
+    let collection_seed = get_collection_seed(&rewarder.collection);
+    let stake_account_seeds = &[
+        collection_seed.as_slice(),
+        &id().to_bytes(),
+        ACCOUNT_PREFIX,
+        &rewarder.key().to_bytes(),
+        &owner.key().to_bytes(),
+        &[stake_account.bump],
+    ];
+
+    let stake_account_signer = &[&stake_account_seeds[..]];
+
+    match rewarder.custody_mode {
+        CustodyMode::TransferAuthority => {
+            if nft_token_account.owner != stake_account.key() {
+                return Err(StakingError::InvalidStakedNFTOwner.into());
+            }
+
+            //transfer nft ownership back to the owner
+            let authority_accounts = SetAuthority {
+                current_authority: stake_account.to_account_info(),
+                account_or_mint: nft_token_account.to_account_info(),
+            };
+            let authority_ctx = CpiContext::new_with_signer(
+                token_program.to_account_info(),
+                authority_accounts,
+                stake_account_signer,
+            );
+            token::set_authority(
+                authority_ctx,
+                AuthorityType::AccountOwner,
+                Some(owner.key()),
+            )?;
+        }
+        CustodyMode::Escrow => {
+            if nft_token_account.owner != owner.key() {
+                return Err(StakingError::InvalidNFTOwner.into());
+            }
+
+            //return the nft from the vault to the owner
+            let transfer_accounts = Transfer {
+                from: nft_vault.to_account_info(),
+                to: nft_token_account.to_account_info(),
+                authority: stake_account.to_account_info(),
+            };
+            let transfer_ctx = CpiContext::new_with_signer(
+                token_program.to_account_info(),
+                transfer_accounts,
+                stake_account_signer,
+            );
+            token::transfer(transfer_ctx, 1)?;
+
+            //close the empty vault and refund its rent to the owner
+            let close_accounts = CloseAccount {
+                account: nft_vault.to_account_info(),
+                destination: owner.to_account_info(),
+                authority: stake_account.to_account_info(),
+            };
+            let close_ctx = CpiContext::new_with_signer(
+                token_program.to_account_info(),
+                close_accounts,
+                stake_account_signer,
+            );
+            token::close_account(close_ctx)?;
+        }
+        CustodyMode::Freeze => {
+            if nft_token_account.owner != owner.key() {
+                return Err(StakingError::InvalidNFTOwner.into());
+            }
+            if !nft_token_account.delegate.contains(&stake_account.key()) {
+                return Err(StakingError::InvalidNFTDelegate.into());
+            }
+
+            let thaw_ix = anchor_metaplex::thaw_delegated_account(
+                stake_account.key(),
+                nft_token_account.key(),
+                nft_edition.key(),
+                nft_mint.key(),
+            );
+            invoke_signed(
+                &thaw_ix,
+                &[
+                    stake_account.to_account_info(),
+                    nft_token_account.to_account_info(),
+                    nft_edition.to_account_info(),
+                    nft_mint.to_account_info(),
+                    token_program.to_account_info(),
+                    token_metadata_program.to_account_info(),
+                ],
+                stake_account_signer,
+            )?;
+
+            //remove the stake account as delegate now that the nft is thawed. Only
+            //the owner can revoke so this is skipped when the owner isn't signing
+            if owner_signed {
+                let revoke_ix = spl_token::instruction::revoke(
+                    &token_program.key(),
+                    &nft_token_account.key(),
+                    &owner.key(),
+                    &[],
+                )?;
+                invoke(
+                    &revoke_ix,
+                    &[
+                        nft_token_account.to_account_info(),
+                        owner.to_account_info(),
+                        token_program.to_account_info(),
+                    ],
+                )?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Calculates the reward earned by a stake account since it was last paid out.
+/// `total_weight` is the combined multiplier of the staked NFTs in basis points.
+pub fn calculate_reward(
+    accrued_reward_per_nft: u128,
+    reward_per_nft_paid: u128,
+    total_weight: u64,
+) -> std::result::Result<u64, ProgramError> {
+    if total_weight == 0 || accrued_reward_per_nft <= reward_per_nft_paid {
+        return Ok(0);
+    }
+
+    let reward = (accrued_reward_per_nft - reward_per_nft_paid)
+        .checked_mul(total_weight as u128)
+        .ok_or(StakingError::RewardOverflow)?
+        / BASE_MULTIPLIER as u128;
+
+    u64::try_from(reward).or(Err(StakingError::RewardOverflow.into()))
+}
+
+/// Returns the reward weight of an NFT in basis points, its weight scaled by
+/// the multiplier of its lockup tier.
+pub fn calculate_weight(weight: u32, multiplier: u32) -> u64 {
+    weight as u64 * multiplier as u64 / BASE_MULTIPLIER as u64
+}
+
+/// Returns how much of `earned` can still be paid out under an emission cap,
+/// where a cap of 0 means the rewarder is uncapped.
+pub fn calculate_capped_reward(earned: u64, emission_cap: u64, total_emitted: u64) -> u64 {
+    if emission_cap == 0 {
+        return earned;
+    }
+
+    earned.min(emission_cap.saturating_sub(total_emitted))
+}
+
+/// Returns the share of `earned` forfeited for a penalty in basis points.
+pub fn calculate_penalty(earned: u64, penalty_bps: u16) -> u64 {
+    (earned as u128 * penalty_bps as u128 / BASE_MULTIPLIER as u128) as u64
+}
+
+/// What happens to the rewards of an NFT that is being unstaked.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Unstake {
+    /// the NFT starts unbonding and its share of the rewards, earned at `weight`,
+    /// is held in its receipt
+    StartUnbonding { weight: u64 },
+    /// the NFT is withdrawn and its held rewards are paid out less the penalty
+    /// for withdrawing early
+    Withdraw { penalty_bps: u16 },
+}
+
+/// Returns how much of the `earned` rewards of a stake account to pay out when
+/// one of its NFTs is unstaked. When the NFT starts unbonding its share since the
+/// stake account was last paid out is moved into `held`, and what it earned since
+/// it was staked at `reward_per_nft_staked` is recorded in `lifetime`. When it is
+/// withdrawn `held` is released less the penalty, which is a share of `lifetime`
+/// taken out of `held` and `earned`, so claiming beforehand doesn't avoid it.
+pub fn settle_unstaked_reward(
+    unstake: Unstake,
+    earned: u64,
+    accrued_reward_per_nft: u128,
+    reward_per_nft_paid: u128,
+    reward_per_nft_staked: u128,
+    held: &mut u64,
+    lifetime: &mut u64,
+) -> std::result::Result<u64, ProgramError> {
+    match unstake {
+        Unstake::StartUnbonding { weight } => {
+            *held = calculate_reward(accrued_reward_per_nft, reward_per_nft_paid, weight)?;
+            *lifetime = calculate_reward(accrued_reward_per_nft, reward_per_nft_staked, weight)?;
+            earned
+                .checked_sub(*held)
+                .ok_or_else(|| StakingError::ArithmeticOverflow.into())
+        }
+        Unstake::Withdraw { penalty_bps } => {
+            let available = earned
+                .checked_add(*held)
+                .ok_or(StakingError::RewardOverflow)?;
+            let forfeited = calculate_penalty(*lifetime, penalty_bps);
+            if forfeited > available {
+                return Err(StakingError::PenaltyNotCovered.into());
+            }
+            if forfeited > 0 {
+                msg!("Withdrawing early forfeits {} reward tokens", forfeited);
+            }
+            *held = 0;
+            *lifetime = 0;
+            Ok(available - forfeited)
+        }
+    }
+}
+
+/// Returns the number of seconds the reward vault can keep paying out rewards
+/// at the current rate, or `None` if nothing is currently being earned.
+pub fn calculate_runway(vault_balance: u64, reward_rate: u64, total_weight: u64) -> Option<u64> {
+    let emission_rate = reward_rate as u128 * total_weight as u128;
+    if emission_rate == 0 {
+        return None;
+    }
+
+    let runway = vault_balance as u128 * BASE_MULTIPLIER as u128 / emission_rate;
+    Some(u64::try_from(runway).unwrap_or(u64::MAX))
+}
+
+/// Pays out up to `earned_reward` tokens to `reward_account` and returns the
+/// amount that was actually paid, which is added to the rewarder's
+/// `total_emitted`. Rewarders can pay out no more than what is left of their
+/// emission cap, and in vault mode no more than what is left in the reward vault.
+/// Nothing is paid out while claiming is paused or while the stake account holds
+/// a blocked NFT.
+#[allow(clippy::too_many_arguments)]
 pub fn transfer_reward<'info>(
     earned_reward: u64,
-    rewarder: &Account<'info, NftStakeRewarder>,
+    rewarder: &mut Account<'info, NftStakeRewarder>,
+    stake_account: &NftStakeAccount,
     reward_mint: &Account<'info, Mint>,
+    reward_vault: &AccountInfo<'info>,
     reward_account: &Account<'info, TokenAccount>,
-    mint_authority: &AccountInfo<'info>,
+    reward_authority: &AccountInfo<'info>,
     token_program: &AccountInfo<'info>,
-) -> ProgramResult {
-    let mint_authority_seeds = &[
-        rewarder.collection.as_bytes(),
+) -> std::result::Result<u64, ProgramError> {
+    if rewarder.paused.claiming {
+        if earned_reward > 0 {
+            msg!("Claiming is paused, {} tokens remain owed", earned_reward);
+        }
+        return Ok(0);
+    }
+    if stake_account.num_blocked > 0 {
+        if earned_reward > 0 {
+            msg!(
+                "A staked NFT is blocked, {} tokens remain owed",
+                earned_reward
+            );
+        }
+        return Ok(0);
+    }
+
+    let to_pay =
+        calculate_capped_reward(earned_reward, rewarder.emission_cap, rewarder.total_emitted);
+    if to_pay < earned_reward {
+        msg!(
+            "Emission cap reached, {} tokens remain owed",
+            earned_reward - to_pay
+        );
+    }
+    if to_pay == 0 {
+        return Ok(0);
+    }
+
+    let collection_seed = get_collection_seed(&rewarder.collection);
+
+    let reward_authority_seeds = &[
+        collection_seed.as_slice(),
         &id().to_bytes(),
         REWARDER_PREFIX,
         &rewarder.key().to_bytes(),
         &[rewarder.reward_authority_bump],
     ];
-    let mint_authority_signer = &[&mint_authority_seeds[..]];
-    let mint_accounts = MintTo {
-        mint: reward_mint.to_account_info(),
-        to: reward_account.to_account_info(),
-        authority: mint_authority.to_account_info(),
+    let paid = pay_reward(
+        to_pay,
+        rewarder.reward_mode,
+        &reward_mint.to_account_info(),
+        reward_vault,
+        &reward_account.to_account_info(),
+        reward_authority,
+        reward_authority_seeds,
+        token_program,
+    )?;
+
+    rewarder.total_emitted = rewarder
+        .total_emitted
+        .checked_add(paid)
+        .ok_or(StakingError::RewardOverflow)?;
+
+    Ok(paid)
+}
+
+/// Mints or transfers up to `amount` reward tokens to `reward_account`, signed by
+/// the reward authority PDA with the given seeds, and returns the amount that was
+/// actually paid. In vault mode no more than the vault balance is paid out.
+#[allow(clippy::too_many_arguments)]
+pub fn pay_reward<'info>(
+    amount: u64,
+    reward_mode: RewardMode,
+    reward_mint: &AccountInfo<'info>,
+    reward_vault: &AccountInfo<'info>,
+    reward_account: &AccountInfo<'info>,
+    reward_authority: &AccountInfo<'info>,
+    reward_authority_seeds: &[&[u8]],
+    token_program: &AccountInfo<'info>,
+) -> std::result::Result<u64, ProgramError> {
+    let reward_authority_signer = &[reward_authority_seeds];
+
+    match reward_mode {
+        RewardMode::Mint => {
+            let mint_accounts = MintTo {
+                mint: reward_mint.clone(),
+                to: reward_account.clone(),
+                authority: reward_authority.clone(),
+            };
+            let mint_ctx = CpiContext::new_with_signer(
+                token_program.clone(),
+                mint_accounts,
+                reward_authority_signer,
+            );
+            token::mint_to(mint_ctx, amount)?;
+
+            Ok(amount)
+        }
+        RewardMode::Vault => {
+            let to_transfer = amount.min(get_vault_balance(reward_vault)?);
+            if to_transfer == 0 {
+                return Ok(0);
+            }
+            if to_transfer < amount {
+                msg!(
+                    "Reward vault ran dry, {} tokens remain owed",
+                    amount - to_transfer
+                );
+            }
+
+            let transfer_accounts = Transfer {
+                from: reward_vault.clone(),
+                to: reward_account.clone(),
+                authority: reward_authority.clone(),
+            };
+            let transfer_ctx = CpiContext::new_with_signer(
+                token_program.clone(),
+                transfer_accounts,
+                reward_authority_signer,
+            );
+            token::transfer(transfer_ctx, to_transfer)?;
+
+            Ok(to_transfer)
+        }
+    }
+}
+
+/// Settles the stake account's rewards for each of the rewarder's reward streams.
+/// They are paid out unless claiming is paused or the stake account holds a blocked
+/// NFT, otherwise they are kept as pending.
+/// The last four remaining accounts for each stream are, in order: the stream's
+/// reward mint, its reward authority PDA, its reward vault and the owner's token
+/// account for the reward mint. When an NFT is being unstaked its share of each
+/// stream is held or released in its receipt like the reward mint's. Streams that
+/// can't pay out, see `can_pay_stream`, keep their rewards pending. Returns
+/// whether any stream had rewards owed.
+pub fn settle_reward_streams<'info>(
+    rewarder: &Account<'info, NftStakeRewarder>,
+    stake_account: &mut NftStakeAccount,
+    remaining_accounts: &[AccountInfo<'info>],
+    token_program: &AccountInfo<'info>,
+    mut unstake: Option<(Unstake, &mut StakeReceipt)>,
+) -> std::result::Result<bool, ProgramError> {
+    let streams = &rewarder.reward_streams;
+    let start = remaining_accounts
+        .len()
+        .checked_sub(streams.len() * 4)
+        .ok_or(StakingError::InvalidRewardStreamAccounts)?;
+    let stream_accounts = &remaining_accounts[start..];
+
+    let total_weight = stake_account.total_weight;
+    let owner = stake_account.owner;
+    let blocked = stake_account.num_blocked > 0;
+    let mut owed = false;
+    for (index, stream) in streams.iter().enumerate() {
+        let checkpoint = &mut stake_account.stream_checkpoints[index];
+        let earned = calculate_reward(
+            stream.accrued_reward_per_nft,
+            checkpoint.reward_per_nft_paid,
+            total_weight,
+        )?
+        .checked_add(checkpoint.pending_reward)
+        .ok_or(StakingError::RewardOverflow)?;
+        owed |= earned > 0;
+        let earned = match &mut unstake {
+            Some((unstake, receipt)) => settle_unstaked_reward(
+                *unstake,
+                earned,
+                stream.accrued_reward_per_nft,
+                checkpoint.reward_per_nft_paid,
+                receipt.stream_rewards_per_nft_staked[index],
+                &mut receipt.unbonding_stream_rewards[index],
+                &mut receipt.lifetime_stream_rewards[index],
+            )?,
+            None => earned,
+        };
+
+        let paid = if !rewarder.paused.claiming && !blocked && earned > 0 {
+            let accounts = &stream_accounts[index * 4..index * 4 + 4];
+            let (reward_mint, reward_authority, reward_vault, reward_account) =
+                (&accounts[0], &accounts[1], &accounts[2], &accounts[3]);
+
+            let collection_seed = get_collection_seed(&rewarder.collection);
+
+            let reward_authority_seeds = &[
+                collection_seed.as_slice(),
+                &id().to_bytes(),
+                REWARDER_PREFIX,
+                &rewarder.key().to_bytes(),
+                &stream.reward_mint.to_bytes(),
+                &[stream.reward_authority_bump],
+            ];
+            let expected_authority = Pubkey::create_program_address(reward_authority_seeds, &id())?;
+            let expected_vault =
+                get_associated_token_address(&expected_authority, &stream.reward_mint);
+            if *reward_mint.key != stream.reward_mint
+                || *reward_authority.key != expected_authority
+                || *reward_vault.key != expected_vault
+            {
+                return Err(StakingError::InvalidRewardStreamAccounts.into());
+            }
+
+            if can_pay_stream(
+                stream,
+                reward_mint,
+                reward_vault,
+                reward_account,
+                &expected_authority,
+                &owner,
+            )? {
+                pay_reward(
+                    earned,
+                    stream.reward_mode,
+                    reward_mint,
+                    reward_vault,
+                    reward_account,
+                    reward_authority,
+                    reward_authority_seeds,
+                    token_program,
+                )?
+            } else {
+                msg!(
+                    "Reward stream {} can't pay out, {} tokens remain owed",
+                    index,
+                    earned
+                );
+                0
+            }
+        } else {
+            0
+        };
+
+        checkpoint.pending_reward = earned - paid;
+        checkpoint.reward_per_nft_paid = stream.accrued_reward_per_nft;
+    }
+
+    Ok(owed)
+}
+
+/// Returns whether a reward stream can pay out to the owner's `reward_account`.
+/// The stream's mint and vault are controlled by whoever funds the stream, so a
+/// mint whose authority was taken away from the stream's reward authority, a
+/// frozen vault, or an owner token account that doesn't exist yet or is frozen
+/// keep the rewards pending rather than failing the instruction. A token account
+/// that belongs to someone else or to another mint is still rejected.
+pub fn can_pay_stream<'info>(
+    stream: &RewardStream,
+    reward_mint: &AccountInfo<'info>,
+    reward_vault: &AccountInfo<'info>,
+    reward_account: &AccountInfo<'info>,
+    reward_authority: &Pubkey,
+    owner: &Pubkey,
+) -> std::result::Result<bool, ProgramError> {
+    let token_account: Account<TokenAccount> = match Account::try_from(reward_account) {
+        Ok(token_account) => token_account,
+        Err(_) => return Ok(false),
     };
-    let mint_ctx = CpiContext::new_with_signer(
-        token_program.to_account_info(),
-        mint_accounts,
-        mint_authority_signer,
-    );
-    token::mint_to(mint_ctx, earned_reward)
+    if token_account.owner != *owner || token_account.mint != stream.reward_mint {
+        return Err(StakingError::InvalidRewardStreamAccounts.into());
+    }
+    if token_account.is_frozen() {
+        return Ok(false);
+    }
+
+    match stream.reward_mode {
+        RewardMode::Mint => {
+            let mint: Account<Mint> = match Account::try_from(reward_mint) {
+                Ok(mint) => mint,
+                Err(_) => return Ok(false),
+            };
+            Ok(mint.mint_authority == COption::Some(*reward_authority))
+        }
+        RewardMode::Vault => {
+            //a vault that hasn't been created yet pays out nothing
+            if reward_vault.data_is_empty() {
+                return Ok(true);
+            }
+            let vault: Account<TokenAccount> = match Account::try_from(reward_vault) {
+                Ok(vault) => vault,
+                Err(_) => return Ok(false),
+            };
+            Ok(!vault.is_frozen())
+        }
+    }
+}
+
+/// Grows a program owned account to `new_len` bytes, topping up its lamports from
+/// `payer` so that it stays rent exempt. solana-program 1.8 has no
+/// `AccountInfo::realloc`, so this does what it does in later versions: the runtime
+/// keeps the data length in the 8 bytes before the account data and leaves room
+/// for `MAX_PERMITTED_DATA_INCREASE` more bytes after it.
+pub fn grow_account<'info>(
+    account: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    rent: &Rent,
+    new_len: usize,
+) -> ProgramResult {
+    if new_len <= account.data_len() {
+        return Ok(());
+    }
+
+    let missing_lamports = rent
+        .minimum_balance(new_len)
+        .saturating_sub(account.lamports());
+    if missing_lamports > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, account.key, missing_lamports),
+            &[payer.clone(), account.clone(), system_program.clone()],
+        )?;
+    }
+
+    resize_account_data(account, new_len)
+}
+
+/// Changes the length of an account's data in place. The new bytes are zeroed.
+pub fn resize_account_data(account: &AccountInfo, new_len: usize) -> ProgramResult {
+    let old_len = account.data_len();
+    if new_len.saturating_sub(old_len) > MAX_PERMITTED_DATA_INCREASE {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let mut data = account.try_borrow_mut_data()?;
+    unsafe {
+        let data_ptr = data.as_mut_ptr();
+        //the serialized length sits right before the data
+        *(data_ptr.offset(-8) as *mut u64) = new_len as u64;
+        *data = std::slice::from_raw_parts_mut(data_ptr, new_len);
+    }
+    if new_len > old_len {
+        data[old_len..].fill(0);
+    }
+
+    Ok(())
+}
+
+/// Reads the token balance of the reward vault. A vault that has not been
+/// created yet holds nothing.
+pub fn get_vault_balance(reward_vault: &AccountInfo) -> std::result::Result<u64, ProgramError> {
+    if reward_vault.data_is_empty() {
+        return Ok(0);
+    }
+
+    let vault = TokenAccount::try_deserialize(&mut &reward_vault.try_borrow_data()?[..])?;
+    Ok(vault.amount)
 }
 
 #[derive(Accounts)]
-#[instruction(_rewarder_bump: u8, reward_authority_bump: u8, reward_rate: u64, collection: String, creators: Vec<CreatorStruct>)]
+#[instruction(_rewarder_bump: u8, reward_authority_bump: u8, reward_rate: u64, collection: String, creators: Vec<CreatorStruct>, nft_update_authority: Pubkey, enforce_metadata: bool, reward_mode: RewardMode, custody_mode: CustodyMode, collection_strategy: CollectionStrategy, pool_id: u64)]
 pub struct InitializeRewarder<'info> {
     /// The new rewarder account to create
     #[account(
         init,
         space = NftStakeRewarder::calculate_len(creators.len(), &collection),
         payer = authority,
-        seeds = [collection.as_bytes(), &id().to_bytes(), REWARDER_PREFIX],
+        seeds = [get_collection_seed(&collection).as_slice(), &id().to_bytes(), REWARDER_PREFIX, &get_pool_seed(pool_id)],
         bump = _rewarder_bump,
     )]
     pub rewarder: Account<'info, NftStakeRewarder>,
@@ -289,19 +1944,21 @@ pub struct InitializeRewarder<'info> {
 
     /// PDA used for minting rewards
     #[account(
-        seeds = [collection.as_bytes(), &id().to_bytes(), REWARDER_PREFIX, &rewarder.key().to_bytes()],
+        seeds = [get_collection_seed(&collection).as_slice(), &id().to_bytes(), REWARDER_PREFIX, &rewarder.key().to_bytes()],
         bump = reward_authority_bump,
     )]
     pub reward_authority: AccountInfo<'info>,
 
     /// The SPL Mint of the reward token. Must have the reward authority mint authority
+    /// when rewards are minted
     #[account(
-        constraint = reward_mint.mint_authority.contains(&reward_authority.key()) @ StakingError::RewarderNotMintAuthority
+        constraint = reward_mode == RewardMode::Vault || reward_mint.mint_authority.contains(&reward_authority.key()) @ StakingError::RewarderNotMintAuthority
     )]
     pub reward_mint: Account<'info, Mint>,
 
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
+    pub clock: Sysvar<'info, Clock>,
 }
 
 #[derive(Accounts)]
@@ -316,6 +1973,198 @@ pub struct UpdateRewardRate<'info> {
     /// The owner of the rewarder account
     #[account(signer)]
     pub authority: AccountInfo<'info>,
+
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateRewarder<'info> {
+    /// The rewarder account to update
+    #[account(
+        mut,
+        has_one = authority @ StakingError::InvalidRewarderAuthority,
+    )]
+    pub rewarder: Account<'info, NftStakeRewarder>,
+
+    /// The owner of the rewarder account
+    #[account(signer)]
+    pub authority: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(reward_authority_bump: u8, reward_rate: u64, reward_mode: RewardMode)]
+pub struct AddRewardStream<'info> {
+    /// The rewarder account to add the reward stream to
+    #[account(
+        mut,
+        has_one = authority @ StakingError::InvalidRewarderAuthority,
+    )]
+    pub rewarder: Account<'info, NftStakeRewarder>,
+
+    /// The owner of the rewarder account
+    #[account(signer)]
+    pub authority: AccountInfo<'info>,
+
+    /// PDA that pays out the stream's rewards
+    #[account(
+        seeds = [get_collection_seed(&rewarder.collection).as_slice(), &id().to_bytes(), REWARDER_PREFIX, &rewarder.key().to_bytes(), &reward_mint.key().to_bytes()],
+        bump = reward_authority_bump,
+    )]
+    pub reward_authority: AccountInfo<'info>,
+
+    /// The SPL Mint of the stream's reward token. Must have the reward authority
+    /// as mint authority when rewards are minted
+    #[account(
+        constraint = reward_mode == RewardMode::Vault || reward_mint.mint_authority.contains(&reward_authority.key()) @ StakingError::RewarderNotMintAuthority
+    )]
+    pub reward_mint: Account<'info, Mint>,
+
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateRewarderCreators<'info> {
+    /// The rewarder account to update
+    #[account(
+        mut,
+        has_one = authority @ StakingError::InvalidRewarderAuthority,
+    )]
+    pub rewarder: Account<'info, NftStakeRewarder>,
+
+    /// The owner of the rewarder account. Pays for the extra space when the
+    /// creators grow
+    #[account(mut, signer)]
+    pub authority: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateRewarder<'info> {
+    /// The rewarder account to upgrade to the current layout
+    #[account(mut, owner = id())]
+    pub rewarder: AccountInfo<'info>,
+
+    /// Pays for the extra space of the current layout
+    #[account(mut, signer)]
+    pub payer: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateStakeAccount<'info> {
+    /// The stake account to upgrade to the current layout
+    #[account(mut, owner = id())]
+    pub stake_account: AccountInfo<'info>,
+
+    /// The rewarder of the stake account, migrated first
+    pub rewarder: Account<'info, NftStakeRewarder>,
+
+    /// Pays for the extra space of the current layout
+    #[account(mut, signer)]
+    pub payer: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(receipt_bump: u8)]
+pub struct MigrateStakedNft<'info> {
+    /// The owner of the stake account. Picks which of the NFTs held by the stake
+    /// account were staked with v0
+    #[account(mut, signer)]
+    pub owner: AccountInfo<'info>,
+
+    /// The rewarder account for the collection
+    pub rewarder: Box<Account<'info, NftStakeRewarder>>,
+
+    /// The migrated stake account for the owner
+    #[account(
+        mut,
+        has_one = rewarder @ StakingError::InvalidRewarder,
+        has_one = owner @ StakingError::InvalidOwnerForStakeAccount,
+    )]
+    pub stake_account: Account<'info, NftStakeAccount>,
+
+    /// The new receipt recording the staked NFT
+    #[account(
+        init,
+        payer = owner,
+        space = StakeReceipt::LEN,
+        seeds = [get_collection_seed(&rewarder.collection).as_slice(), &id().to_bytes(), RECEIPT_PREFIX, &stake_account.key().to_bytes(), &nft_mint.key().to_bytes()],
+        bump = receipt_bump,
+    )]
+    pub stake_receipt: Box<Account<'info, StakeReceipt>>,
+
+    /// The Mint of the NFT
+    pub nft_mint: Box<Account<'info, Mint>>,
+
+    /// The NFT token account that v0 transferred ownership of to the stake account
+    #[account(
+        constraint = nft_token_account.owner == stake_account.key() @ StakingError::InvalidNFTOwner,
+        constraint = nft_token_account.mint == nft_mint.key() @ StakingError::InvalidNFTAccountMint,
+        constraint = nft_token_account.amount == 1 @ StakingError::NFTAccountEmpty,
+    )]
+    pub nft_token_account: Account<'info, TokenAccount>,
+
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    /// The rewarder account to take ownership of
+    #[account(
+        mut,
+        constraint = rewarder.pending_authority == pending_authority.key() @ StakingError::InvalidPendingAuthority,
+    )]
+    pub rewarder: Account<'info, NftStakeRewarder>,
+
+    /// The proposed new owner of the rewarder account
+    #[account(signer)]
+    pub pending_authority: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct FundRewarder<'info> {
+    /// The account sending reward tokens to the vault
+    #[account(signer)]
+    pub funder: AccountInfo<'info>,
+
+    /// The rewarder to fund
+    #[account(
+        constraint = rewarder.reward_mode == RewardMode::Vault @ StakingError::RewarderNotVaultMode,
+    )]
+    pub rewarder: Account<'info, NftStakeRewarder>,
+
+    /// PDA that owns the reward vault
+    #[account(
+        seeds = [get_collection_seed(&rewarder.collection).as_slice(), &id().to_bytes(), REWARDER_PREFIX, &rewarder.key().to_bytes()],
+        bump = rewarder.reward_authority_bump,
+    )]
+    pub reward_authority: AccountInfo<'info>,
+
+    /// The token account that rewards are paid out of
+    #[account(
+        mut,
+        address = get_associated_token_address(&reward_authority.key(), &rewarder.reward_mint) @ StakingError::InvalidRewardVault,
+    )]
+    pub reward_vault: Account<'info, TokenAccount>,
+
+    /// The token account the reward tokens are sent from
+    #[account(
+        mut,
+        constraint = funder_token_account.mint == rewarder.reward_mint @ StakingError::InvalidRewardTokenAccount,
+    )]
+    pub funder_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
 }
 
 #[derive(Accounts)]
@@ -330,7 +2179,7 @@ pub struct InitializeStakeAccount<'info> {
         init,
         payer = owner,
         space = NftStakeAccount::LEN,
-        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), ACCOUNT_PREFIX, &rewarder.key().to_bytes(), &owner.key().to_bytes()],
+        seeds = [get_collection_seed(&rewarder.collection).as_slice(), &id().to_bytes(), ACCOUNT_PREFIX, &rewarder.key().to_bytes(), &owner.key().to_bytes()],
         bump = bump,
     )]
     pub stake_account: Account<'info, NftStakeAccount>,
@@ -343,7 +2192,7 @@ pub struct InitializeStakeAccount<'info> {
 }
 
 #[derive(Accounts)]
-// #[instruction(_vault_bump: u8)]
+#[instruction(receipt_bump: u8, lockup_tier: u8, weight: u32, weight_proof: Vec<[u8; 32]>)]
 pub struct StakeNft<'info> {
     /// The owner of the stake account
     #[account(mut, signer)]
@@ -355,21 +2204,38 @@ pub struct StakeNft<'info> {
 
     /// PDA that has the authority to mint reward tokens
     #[account(
-        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), REWARDER_PREFIX, &rewarder.key().to_bytes()],
+        seeds = [get_collection_seed(&rewarder.collection).as_slice(), &id().to_bytes(), REWARDER_PREFIX, &rewarder.key().to_bytes()],
         bump = rewarder.reward_authority_bump,
     )]
     pub reward_authority: AccountInfo<'info>,
 
+    /// The token account that rewards are paid out of in vault mode
+    #[account(
+        mut,
+        address = get_associated_token_address(&reward_authority.key(), &rewarder.reward_mint) @ StakingError::InvalidRewardVault,
+    )]
+    pub reward_vault: AccountInfo<'info>,
+
     /// The stake account for the owner
     #[account(
         mut,
         has_one = rewarder @ StakingError::InvalidRewarder,
         has_one = owner @ StakingError::InvalidOwnerForStakeAccount,
-        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), ACCOUNT_PREFIX, &rewarder.key().to_bytes(), &owner.key().to_bytes()],
+        seeds = [get_collection_seed(&rewarder.collection).as_slice(), &id().to_bytes(), ACCOUNT_PREFIX, &rewarder.key().to_bytes(), &owner.key().to_bytes()],
         bump = stake_account.bump,
     )]
     pub stake_account: Account<'info, NftStakeAccount>,
 
+    /// The new receipt recording the staked NFT
+    #[account(
+        init,
+        payer = owner,
+        space = StakeReceipt::LEN,
+        seeds = [get_collection_seed(&rewarder.collection).as_slice(), &id().to_bytes(), RECEIPT_PREFIX, &stake_account.key().to_bytes(), &nft_mint.key().to_bytes()],
+        bump = receipt_bump,
+    )]
+    pub stake_receipt: Box<Account<'info, StakeReceipt>>,
+
     /// The Mint of the rewarded token
     #[account(
         mut,
@@ -400,7 +2266,30 @@ pub struct StakeNft<'info> {
     )]
     pub nft_token_account: Account<'info, TokenAccount>,
 
+    /// The stake account's token account for the NFT. Holds the NFT while it is
+    /// staked with a rewarder using escrow custody
+    #[account(
+        mut,
+        address = get_associated_token_address(&stake_account.key(), &nft_mint.key()) @ StakingError::InvalidNFTVault,
+    )]
+    pub nft_vault: AccountInfo<'info>,
+
+    /// The Metaplex edition of the NFT. Proves the mint is an NFT when metadata is
+    /// enforced and freezes the NFT in place with freeze custody
+    #[account(
+        address = anchor_metaplex::get_edition_address(&nft_mint.key()) @ StakingError::InvalidNFTEdition,
+    )]
+    pub nft_edition: AccountInfo<'info>,
+
+    /// The PDA that blocks the NFT mint from being staked. Must not exist
+    #[account(
+        address = get_blocked_mint_address(&rewarder.collection, &rewarder.key(), &nft_mint.key()),
+    )]
+    pub blocked_mint: AccountInfo<'info>,
+
     pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_metadata_program: Program<'info, MetaplexTokenMetadata>,
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
     pub clock: Sysvar<'info, Clock>,
@@ -418,21 +2307,34 @@ pub struct UnstakeNft<'info> {
 
     /// PDA that has the authority to mint reward tokens
     #[account(
-        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), REWARDER_PREFIX, &rewarder.key().to_bytes()],
+        seeds = [get_collection_seed(&rewarder.collection).as_slice(), &id().to_bytes(), REWARDER_PREFIX, &rewarder.key().to_bytes()],
         bump = rewarder.reward_authority_bump,
     )]
     pub reward_authority: AccountInfo<'info>,
 
+    /// The token account that rewards are paid out of in vault mode
+    #[account(
+        mut,
+        address = get_associated_token_address(&reward_authority.key(), &rewarder.reward_mint) @ StakingError::InvalidRewardVault,
+    )]
+    pub reward_vault: AccountInfo<'info>,
+
     /// The stake account for the owner
     #[account(
         mut,
         has_one = rewarder @ StakingError::InvalidRewarder,
         has_one = owner @ StakingError::InvalidOwnerForStakeAccount,
-        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), ACCOUNT_PREFIX, &rewarder.key().to_bytes(), &owner.key().to_bytes()],
+        seeds = [get_collection_seed(&rewarder.collection).as_slice(), &id().to_bytes(), ACCOUNT_PREFIX, &rewarder.key().to_bytes(), &owner.key().to_bytes()],
         bump = stake_account.bump,
     )]
     pub stake_account: Account<'info, NftStakeAccount>,
 
+    /// The receipt for the staked NFT. Marked as unbonding when the rewarder has
+    /// a cooldown, otherwise closed and its rent refunded to the owner once the
+    /// NFT is withdrawn. Only closed conditionally so it is checked in the handler
+    #[account(mut)]
+    pub stake_receipt: AccountInfo<'info>,
+
     /// The Mint of the rewarded token
     #[account(
         mut,
@@ -454,16 +2356,154 @@ pub struct UnstakeNft<'info> {
     )]
     pub nft_mint: Box<Account<'info, Mint>>,
 
-    /// The token account from the owner
+    /// The token account the NFT was staked from. With escrow custody this can
+    /// be any token account for the NFT mint that belongs to the owner
+    #[account(
+        mut,
+        constraint = nft_token_account.mint == nft_mint.key() @ StakingError::InvalidNFTAccountMint,
+    )]
+    pub nft_token_account: Account<'info, TokenAccount>,
+
+    /// The stake account's token account that holds the NFT with escrow custody
+    #[account(
+        mut,
+        address = get_associated_token_address(&stake_account.key(), &nft_mint.key()) @ StakingError::InvalidNFTVault,
+    )]
+    pub nft_vault: AccountInfo<'info>,
+
+    /// The Metaplex edition of the NFT. Thaws the NFT with freeze custody
+    #[account(
+        address = anchor_metaplex::get_edition_address(&nft_mint.key()) @ StakingError::InvalidNFTEdition,
+    )]
+    pub nft_edition: AccountInfo<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub token_metadata_program: Program<'info, MetaplexTokenMetadata>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+#[instruction(bump: u8)]
+pub struct BlockMint<'info> {
+    /// The rewarder account to block the mint from
+    #[account(
+        mut,
+        has_one = authority @ StakingError::InvalidRewarderAuthority,
+    )]
+    pub rewarder: Account<'info, NftStakeRewarder>,
+
+    /// The owner of the rewarder account
+    #[account(mut, signer)]
+    pub authority: AccountInfo<'info>,
+
+    /// The Mint of the NFT to block
+    pub nft_mint: AccountInfo<'info>,
+
+    /// The new PDA that blocks the mint
+    #[account(
+        init,
+        payer = authority,
+        space = BlockedMint::LEN,
+        seeds = [get_collection_seed(&rewarder.collection).as_slice(), &id().to_bytes(), BLOCKED_PREFIX, &rewarder.key().to_bytes(), &nft_mint.key().to_bytes()],
+        bump = bump,
+    )]
+    pub blocked_mint: Account<'info, BlockedMint>,
+
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct UnblockMint<'info> {
+    /// The rewarder account the mint is blocked from
+    #[account(
+        mut,
+        has_one = authority @ StakingError::InvalidRewarderAuthority,
+    )]
+    pub rewarder: Account<'info, NftStakeRewarder>,
+
+    /// The owner of the rewarder account. Receives the rent of the closed PDA
+    #[account(mut, signer)]
+    pub authority: AccountInfo<'info>,
+
+    /// The PDA that blocks the mint
+    #[account(
+        mut,
+        close = authority,
+        has_one = rewarder @ StakingError::InvalidRewarder,
+        seeds = [get_collection_seed(&rewarder.collection).as_slice(), &id().to_bytes(), BLOCKED_PREFIX, &rewarder.key().to_bytes(), &blocked_mint.mint.to_bytes()],
+        bump = blocked_mint.bump,
+    )]
+    pub blocked_mint: Account<'info, BlockedMint>,
+
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct ForceUnstake<'info> {
+    /// The rewarder account for the collection
+    #[account(
+        mut,
+        has_one = authority @ StakingError::InvalidRewarderAuthority,
+    )]
+    pub rewarder: Account<'info, NftStakeRewarder>,
+
+    /// The owner of the rewarder account
+    #[account(signer)]
+    pub authority: AccountInfo<'info>,
+
+    /// The PDA that blocks the NFT mint. Only blocked mints can be force unstaked
+    #[account(
+        mut,
+        address = get_blocked_mint_address(&rewarder.collection, &rewarder.key(), &nft_mint.key()),
+    )]
+    pub blocked_mint: AccountInfo<'info>,
+
+    /// The owner of the stake account. Receives the NFT and the rent of the receipt
+    #[account(mut)]
+    pub owner: AccountInfo<'info>,
+
+    /// The stake account the NFT is staked with
+    #[account(
+        mut,
+        has_one = rewarder @ StakingError::InvalidRewarder,
+        has_one = owner @ StakingError::InvalidOwnerForStakeAccount,
+        seeds = [get_collection_seed(&rewarder.collection).as_slice(), &id().to_bytes(), ACCOUNT_PREFIX, &rewarder.key().to_bytes(), &owner.key().to_bytes()],
+        bump = stake_account.bump,
+    )]
+    pub stake_account: Account<'info, NftStakeAccount>,
+
+    /// The receipt for the staked NFT. Closed and its rent refunded to the owner
+    #[account(mut)]
+    pub stake_receipt: AccountInfo<'info>,
+
+    /// The Mint of the NFT
+    pub nft_mint: Box<Account<'info, Mint>>,
+
+    /// The token account the NFT is returned to. With escrow custody this can
+    /// be any token account for the NFT mint that belongs to the owner
     #[account(
         mut,
-        constraint = nft_token_account.owner == stake_account.key() @ StakingError::InvalidStakedNFTOwner,
         constraint = nft_token_account.mint == nft_mint.key() @ StakingError::InvalidNFTAccountMint,
-        address = get_associated_token_address(&owner.key(), &nft_mint.key()),
     )]
     pub nft_token_account: Account<'info, TokenAccount>,
 
+    /// The stake account's token account that holds the NFT with escrow custody
+    #[account(
+        mut,
+        address = get_associated_token_address(&stake_account.key(), &nft_mint.key()) @ StakingError::InvalidNFTVault,
+    )]
+    pub nft_vault: AccountInfo<'info>,
+
+    /// The Metaplex edition of the NFT. Thaws the NFT with freeze custody
+    #[account(
+        address = anchor_metaplex::get_edition_address(&nft_mint.key()) @ StakingError::InvalidNFTEdition,
+    )]
+    pub nft_edition: AccountInfo<'info>,
+
     pub token_program: Program<'info, Token>,
+    pub token_metadata_program: Program<'info, MetaplexTokenMetadata>,
     pub clock: Sysvar<'info, Clock>,
 }
 
@@ -474,7 +2514,7 @@ pub struct Claim<'info> {
     pub owner: AccountInfo<'info>,
 
     /// The rewarder account for the collection
-    #[account()]
+    #[account(mut)]
     pub rewarder: Account<'info, NftStakeRewarder>,
 
     /// The stake account for the owner
@@ -482,7 +2522,7 @@ pub struct Claim<'info> {
         mut,
         has_one = rewarder @ StakingError::InvalidRewarder,
         has_one = owner @ StakingError::InvalidOwnerForStakeAccount,
-        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), ACCOUNT_PREFIX, &rewarder.key().to_bytes(), &owner.key().to_bytes()],
+        seeds = [get_collection_seed(&rewarder.collection).as_slice(), &id().to_bytes(), ACCOUNT_PREFIX, &rewarder.key().to_bytes(), &owner.key().to_bytes()],
         bump = stake_account.bump,
     )]
     pub stake_account: Account<'info, NftStakeAccount>,
@@ -504,11 +2544,18 @@ pub struct Claim<'info> {
 
     /// PDA that has the authority to mint reward tokens
     #[account(
-        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), REWARDER_PREFIX, &rewarder.key().to_bytes()],
+        seeds = [get_collection_seed(&rewarder.collection).as_slice(), &id().to_bytes(), REWARDER_PREFIX, &rewarder.key().to_bytes()],
         bump = rewarder.reward_authority_bump,
     )]
     pub reward_authority: AccountInfo<'info>,
 
+    /// The token account that rewards are paid out of in vault mode
+    #[account(
+        mut,
+        address = get_associated_token_address(&reward_authority.key(), &rewarder.reward_mint) @ StakingError::InvalidRewardVault,
+    )]
+    pub reward_vault: AccountInfo<'info>,
+
     pub token_program: Program<'info, Token>,
     pub clock: Sysvar<'info, Clock>,
 }
@@ -531,37 +2578,135 @@ pub fn check_metadata<'a, 'b, 'c, 'info>(
         return Err(StakingError::InvalidMetadataAccountAddress.into());
     }
 
-    if metadata.update_authority != rewarder.allowed_update_authority {
-        return Err(StakingError::InvalidMetadataUpdateAuthority.into());
+    let policy = &rewarder.metadata_policy;
+
+    if policy.check_update_authority {
+        check_update_authority(&metadata.update_authority, rewarder)?;
     }
 
-    if !metadata.data.name.starts_with(&rewarder.collection) {
-        return Err(StakingError::InvalidMetadataCollectionPrefix.into());
+    if policy.check_collection {
+        check_collection(metadata, rewarder)?;
     }
 
-    if let Some(creators) = &metadata.data.creators {
-        if creators.len() != rewarder.creators.len() {
-            return Err(StakingError::InvalidMetadataCreators.into());
+    if policy.check_creators {
+        check_creators(
+            metadata.data.creators.as_deref(),
+            &rewarder.creators,
+            policy,
+        )?;
+    }
+
+    Ok(())
+}
+
+pub fn check_update_authority(
+    update_authority: &Pubkey,
+    rewarder: &NftStakeRewarder,
+) -> std::result::Result<(), ProgramError> {
+    let allowed = *update_authority == rewarder.allowed_update_authority
+        || rewarder
+            .additional_update_authorities
+            .contains(update_authority);
+
+    if !allowed {
+        return Err(StakingError::InvalidMetadataUpdateAuthority.into());
+    }
+
+    Ok(())
+}
+
+pub fn check_collection(
+    metadata: &MetadataAccount,
+    rewarder: &NftStakeRewarder,
+) -> std::result::Result<(), ProgramError> {
+    match rewarder.collection_strategy {
+        CollectionStrategy::NamePrefix => {
+            if !metadata.data.name.starts_with(&rewarder.collection) {
+                return Err(StakingError::InvalidMetadataCollectionPrefix.into());
+            }
         }
+        CollectionStrategy::VerifiedCollection { mint } => match &metadata.collection {
+            Some(collection) if collection.verified && collection.key == mint => {}
+            _ => return Err(StakingError::InvalidMetadataCollection.into()),
+        },
+    }
+
+    Ok(())
+}
 
-        for creator in creators.iter() {
-            let found_match = rewarder
-                .creators
+pub fn check_creators(
+    creators: Option<&[Creator]>,
+    known_creators: &[CreatorStruct],
+    policy: &MetadataPolicy,
+) -> std::result::Result<(), ProgramError> {
+    let creators = creators.ok_or(StakingError::InvalidMetadataCreators)?;
+    let is_known = |creator: &&Creator| known_creators.iter().any(|known| &known == *creator);
+
+    match policy.creator_match {
+        CreatorMatch::All => {
+            if creators.len() != known_creators.len() || !creators.iter().all(|c| is_known(&c)) {
+                return Err(StakingError::InvalidMetadataCreators.into());
+            }
+            if policy.verified_creators_only && !creators.iter().all(|c| c.verified) {
+                return Err(StakingError::UnverifiedMetadataCreators.into());
+            }
+        }
+        CreatorMatch::Any => {
+            let mut matching = creators.iter().filter(is_known).peekable();
+            if matching.peek().is_none() {
+                return Err(StakingError::InvalidMetadataCreators.into());
+            }
+            if policy.verified_creators_only && !matching.any(|c| c.verified) {
+                return Err(StakingError::UnverifiedMetadataCreators.into());
+            }
+        }
+        CreatorMatch::AnyVerified => {
+            let mut trusted = creators
                 .iter()
-                .find(|known_creator| known_creator == creator);
-            if found_match.is_none() {
+                .filter(|creator| {
+                    known_creators
+                        .iter()
+                        .any(|known| known.address == creator.address)
+                })
+                .peekable();
+            if trusted.peek().is_none() {
                 return Err(StakingError::InvalidMetadataCreators.into());
             }
+            if !trusted.any(|creator| creator.verified) {
+                return Err(StakingError::UnverifiedMetadataCreators.into());
+            }
         }
-    } else {
-        return Err(StakingError::InvalidMetadataCreators.into());
     }
 
     Ok(())
 }
 
-pub fn get_metadata_account<'a, 'b>(
-    accounts: &'a [AccountInfo<'b>],
+/// Checks that a mint is a real NFT, backed by a Metaplex master or print
+/// edition that holds its mint authority.
+pub fn check_edition(
+    edition: &AccountInfo,
+    decimals: u8,
+    mint_authority: COption<Pubkey>,
+) -> std::result::Result<(), ProgramError> {
+    if *edition.owner != anchor_metaplex::ID || !anchor_metaplex::is_edition(&edition.data.borrow())
+    {
+        return Err(StakingError::InvalidNFTEditionAccount.into());
+    }
+
+    if decimals != 0 {
+        return Err(StakingError::InvalidNFTMintDecimals.into());
+    }
+
+    //creating an edition moves the mint authority to the edition
+    if mint_authority.is_some() && mint_authority != COption::Some(edition.key()) {
+        return Err(StakingError::InvalidNFTMintAuthority.into());
+    }
+
+    Ok(())
+}
+
+pub fn get_metadata_account<'b>(
+    accounts: &[AccountInfo<'b>],
 ) -> std::result::Result<Account<'b, MetadataAccount>, StakingError> {
     let accounts_iter = &mut accounts.iter();
     let metadata_info =
@@ -571,32 +2716,1112 @@ pub fn get_metadata_account<'a, 'b>(
         return Err(StakingError::MetadataAccountNotOwnedByCorrectProgram);
     }
 
-    Ok(Account::try_from_unchecked(&metadata_info)
-        .or(Err(StakingError::InvalidMetadataAccountData))?)
+    Account::try_from_unchecked(metadata_info).or(Err(StakingError::InvalidMetadataAccountData))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     pub fn test_reward_calculation() {
-        let current_time = 100_i64;
-        let reward_rate = 10_u64;
-        let last_claimed = 0_i64;
-        let mut num_staked = 0;
+        let accrued_reward_per_nft = 1000_u128;
+        let reward_per_nft_paid = 0_u128;
+        let mut total_weight = 0;
 
-        //if num staked is 0 always return 0 rewards
-        let earned_rewared = calculate_reward(reward_rate, num_staked, last_claimed, current_time);
+        //if nothing is staked always return 0 rewards
+        let earned_rewared =
+            calculate_reward(accrued_reward_per_nft, reward_per_nft_paid, total_weight).unwrap();
         assert_eq!(earned_rewared, 0);
 
-        num_staked += 1;
-        let earned_rewared = calculate_reward(reward_rate, num_staked, last_claimed, current_time);
+        total_weight += BASE_MULTIPLIER as u64;
+        let earned_rewared =
+            calculate_reward(accrued_reward_per_nft, reward_per_nft_paid, total_weight).unwrap();
         assert_eq!(earned_rewared, 1000);
 
         //twice the number staked recieves twice the reward
-        num_staked += 1;
-        let earned_rewared = calculate_reward(reward_rate, num_staked, last_claimed, current_time);
+        total_weight += BASE_MULTIPLIER as u64;
+        let earned_rewared =
+            calculate_reward(accrued_reward_per_nft, reward_per_nft_paid, total_weight).unwrap();
         assert_eq!(earned_rewared, 2000);
+
+        //rewards already paid out are not earned again
+        let earned_rewared = calculate_reward(accrued_reward_per_nft, 400, total_weight).unwrap();
+        assert_eq!(earned_rewared, 1200);
+
+        //a 1.5x multiplier on one nft earns half as much again for that nft
+        total_weight += 5_000;
+        let earned_rewared =
+            calculate_reward(accrued_reward_per_nft, reward_per_nft_paid, total_weight).unwrap();
+        assert_eq!(earned_rewared, 2500);
+    }
+
+    #[test]
+    pub fn test_rate_change_is_not_retroactive() {
+        let mut rewarder = NftStakeRewarder {
+            reward_rate: 10,
+            last_accrued: 0,
+            ..Default::default()
+        };
+
+        update_accrued_reward(&mut rewarder, 100).unwrap();
+        assert_eq!(rewarder.accrued_reward_per_nft, 1000);
+
+        //the new rate only applies to time after the update
+        rewarder.reward_rate = 1;
+        update_accrued_reward(&mut rewarder, 150).unwrap();
+        assert_eq!(rewarder.accrued_reward_per_nft, 1050);
+
+        //time moving backwards never reduces the accumulator
+        update_accrued_reward(&mut rewarder, 120).unwrap();
+        assert_eq!(rewarder.accrued_reward_per_nft, 1050);
+        assert_eq!(rewarder.last_accrued, 150);
+
+        assert_eq!(
+            calculate_reward(
+                rewarder.accrued_reward_per_nft,
+                1000,
+                BASE_MULTIPLIER as u64
+            )
+            .unwrap(),
+            50
+        );
+    }
+
+    #[test]
+    pub fn test_accrual_pause() {
+        let mut rewarder = NftStakeRewarder {
+            reward_rate: 10,
+            last_accrued: 0,
+            ..Default::default()
+        };
+
+        update_accrued_reward(&mut rewarder, 100).unwrap();
+        assert_eq!(rewarder.accrued_reward_per_nft, 1000);
+
+        //paused time earns nothing but still moves the accumulator forward
+        rewarder.paused.accrual = true;
+        update_accrued_reward(&mut rewarder, 200).unwrap();
+        assert_eq!(rewarder.accrued_reward_per_nft, 1000);
+        assert_eq!(rewarder.last_accrued, 200);
+
+        //unpausing doesn't pay out for the paused time
+        rewarder.paused.accrual = false;
+        update_accrued_reward(&mut rewarder, 250).unwrap();
+        assert_eq!(rewarder.accrued_reward_per_nft, 1500);
+    }
+
+    #[test]
+    pub fn test_reward_stream_accrual() {
+        let mut rewarder = NftStakeRewarder {
+            reward_rate: 10,
+            last_accrued: 0,
+            reward_streams: vec![RewardStream {
+                reward_rate: 3,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        //every stream accrues at its own rate
+        update_accrued_reward(&mut rewarder, 100).unwrap();
+        assert_eq!(rewarder.accrued_reward_per_nft, 1000);
+        assert_eq!(rewarder.reward_streams[0].accrued_reward_per_nft, 300);
+
+        //streams follow the rewarder's schedule and pause flags
+        rewarder.paused.accrual = true;
+        update_accrued_reward(&mut rewarder, 200).unwrap();
+        assert_eq!(rewarder.reward_streams[0].accrued_reward_per_nft, 300);
+
+        rewarder.paused.accrual = false;
+        rewarder.schedule.end_time = 250;
+        update_accrued_reward(&mut rewarder, 300).unwrap();
+        assert_eq!(rewarder.accrued_reward_per_nft, 1500);
+        assert_eq!(rewarder.reward_streams[0].accrued_reward_per_nft, 450);
+    }
+
+    #[test]
+    pub fn test_settle_pending_reward() {
+        let mut rewarder = NftStakeRewarder {
+            reward_rate: 10,
+            last_accrued: 0,
+            reward_streams: vec![RewardStream {
+                reward_rate: 3,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let mut stake_account = NftStakeAccount {
+            owner: Pubkey::new_unique(),
+            rewarder: Pubkey::new_unique(),
+            num_staked: 2,
+            total_weight: 2 * BASE_MULTIPLIER as u64,
+            bump: 255,
+            last_claimed: 0,
+            reward_per_nft_paid: 0,
+            pending_reward: 0,
+            stream_checkpoints: [StreamCheckpoint::default(); MAX_REWARD_STREAMS],
+            version: STAKE_ACCOUNT_VERSION,
+            v0_staked: 0,
+            num_blocked: 0,
+            reserved: Default::default(),
+        };
+
+        settle_pending_reward(&mut rewarder, &mut stake_account, 100).unwrap();
+        assert_eq!(stake_account.pending_reward, 2000);
+        assert_eq!(stake_account.reward_per_nft_paid, 1000);
+        assert_eq!(stake_account.stream_checkpoints[0].pending_reward, 600);
+        assert_eq!(stake_account.stream_checkpoints[0].reward_per_nft_paid, 300);
+
+        //a blocked nft's weight is taken out once it is settled, so it earns nothing more
+        stake_account.total_weight -= BASE_MULTIPLIER as u64;
+        settle_pending_reward(&mut rewarder, &mut stake_account, 200).unwrap();
+        assert_eq!(stake_account.pending_reward, 3000);
+        assert_eq!(stake_account.stream_checkpoints[0].pending_reward, 900);
+    }
+
+    #[test]
+    pub fn test_emission_schedule() {
+        //the default schedule emits at the full rate
+        let schedule = EmissionSchedule::default();
+        assert_eq!(calculate_emission(10, &schedule, -50, 100).unwrap(), 1500);
+
+        //nothing is emitted before the start or after the end
+        let schedule = EmissionSchedule {
+            start_time: 100,
+            end_time: 200,
+            halving_interval: 0,
+        };
+        assert_eq!(calculate_emission(10, &schedule, 0, 100).unwrap(), 0);
+        assert_eq!(calculate_emission(10, &schedule, 50, 150).unwrap(), 500);
+        assert_eq!(calculate_emission(10, &schedule, 150, 300).unwrap(), 500);
+        assert_eq!(calculate_emission(10, &schedule, 200, 300).unwrap(), 0);
+
+        //each halving interval is emitted at its own rate
+        let schedule = EmissionSchedule {
+            start_time: 100,
+            end_time: 0,
+            halving_interval: 10,
+        };
+        assert_eq!(calculate_emission(8, &schedule, 100, 110).unwrap(), 80);
+        assert_eq!(
+            calculate_emission(8, &schedule, 105, 125).unwrap(),
+            40 + 40 + 10
+        );
+        assert_eq!(
+            calculate_emission(8, &schedule, 0, 1_000_000).unwrap(),
+            80 + 40 + 20 + 10
+        );
+
+        //splitting a range across a boundary emits the same as the whole range
+        let whole = calculate_emission(8, &schedule, 103, 137).unwrap();
+        let split = calculate_emission(8, &schedule, 103, 115).unwrap()
+            + calculate_emission(8, &schedule, 115, 137).unwrap();
+        assert_eq!(whole, split);
+
+        //the rate never halves below nothing
+        assert_eq!(
+            calculate_emission(u64::MAX, &schedule, 0, i64::MAX).unwrap(),
+            (0..64)
+                .map(|halvings| (u64::MAX >> halvings) as u128 * 10)
+                .sum::<u128>()
+        );
+    }
+
+    #[test]
+    pub fn test_reward_overflow() {
+        let overflow: ProgramError = StakingError::RewardOverflow.into();
+
+        let base = BASE_MULTIPLIER as u64;
+
+        //the largest reward that fits in a u64 is still paid
+        let earned_rewared = calculate_reward(u64::MAX as u128, 0, base).unwrap();
+        assert_eq!(earned_rewared, u64::MAX);
+
+        //anything larger is an error instead of wrapping
+        let result = calculate_reward(u64::MAX as u128, 0, base + 1);
+        assert_eq!(result.unwrap_err(), overflow);
+        let result = calculate_reward(u128::MAX, 0, u64::MAX);
+        assert_eq!(result.unwrap_err(), overflow);
+
+        //a max rate over a long idle period fits in the accumulator
+        let mut rewarder = NftStakeRewarder {
+            reward_rate: u64::MAX,
+            last_accrued: i64::MIN,
+            ..Default::default()
+        };
+        update_accrued_reward(&mut rewarder, -1).unwrap();
+        assert_eq!(
+            rewarder.accrued_reward_per_nft,
+            u64::MAX as u128 * i64::MAX as u128
+        );
+
+        //but an accumulator that would overflow is rejected and left untouched
+        rewarder.accrued_reward_per_nft = u128::MAX;
+        let result = update_accrued_reward(&mut rewarder, 0);
+        assert_eq!(result.unwrap_err(), overflow);
+        assert_eq!(rewarder.last_accrued, -1);
+
+        //as is a clock that can't be subtracted
+        rewarder.last_accrued = i64::MIN;
+        let result = update_accrued_reward(&mut rewarder, i64::MAX);
+        assert_eq!(result.unwrap_err(), StakingError::ArithmeticOverflow.into());
+    }
+
+    #[test]
+    pub fn test_weight_calculation() {
+        let base = BASE_MULTIPLIER;
+
+        assert_eq!(calculate_weight(base, base), base as u64);
+        //a 2x rarity weight staked in a 1.5x lockup tier earns 3x
+        assert_eq!(calculate_weight(2 * base, 15_000), 3 * base as u64);
+        assert_eq!(calculate_weight(0, 15_000), 0);
+        assert_eq!(
+            calculate_weight(u32::MAX, u32::MAX),
+            u32::MAX as u64 * u32::MAX as u64 / base as u64
+        );
+    }
+
+    #[test]
+    pub fn test_weight_proof() {
+        let mints: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+        let leaves: Vec<[u8; 32]> = mints
+            .iter()
+            .zip([10_000, 20_000, 50_000])
+            .map(|(mint, weight)| merkle::weight_leaf(mint, weight))
+            .collect();
+
+        // root
+        //  ├─ ab
+        //  │   ├─ a
+        //  │   └─ b
+        //  └─ c
+        let ab = merkle::hash_pair(&leaves[0], &leaves[1]);
+        let root = merkle::hash_pair(&ab, &leaves[2]);
+
+        assert!(merkle::verify_proof(
+            &[leaves[1], leaves[2]],
+            &root,
+            leaves[0]
+        ));
+        assert!(merkle::verify_proof(
+            &[leaves[0], leaves[2]],
+            &root,
+            leaves[1]
+        ));
+        assert!(merkle::verify_proof(&[ab], &root, leaves[2]));
+
+        //claiming a different weight for a mint fails
+        let inflated = merkle::weight_leaf(&mints[0], 50_000);
+        assert!(!merkle::verify_proof(
+            &[leaves[1], leaves[2]],
+            &root,
+            inflated
+        ));
+        //as does a mint that isn't in the tree
+        let unknown = merkle::weight_leaf(&Pubkey::new_unique(), 10_000);
+        assert!(!merkle::verify_proof(
+            &[leaves[1], leaves[2]],
+            &root,
+            unknown
+        ));
+        assert!(!merkle::verify_proof(&[], &root, leaves[0]));
+    }
+
+    #[test]
+    pub fn test_allowlist_proof() {
+        let mints: Vec<Pubkey> = (0..4).map(|_| Pubkey::new_unique()).collect();
+        let leaves: Vec<[u8; 32]> = mints.iter().map(merkle::mint_leaf).collect();
+        let ab = merkle::hash_pair(&leaves[0], &leaves[1]);
+        let cd = merkle::hash_pair(&leaves[2], &leaves[3]);
+        let root = merkle::hash_pair(&ab, &cd);
+
+        for (i, leaf) in leaves.iter().enumerate() {
+            let sibling = leaves[i ^ 1];
+            let uncle = if i < 2 { cd } else { ab };
+            assert!(merkle::verify_proof(&[sibling, uncle], &root, *leaf));
+        }
+
+        let unknown = merkle::mint_leaf(&Pubkey::new_unique());
+        assert!(!merkle::verify_proof(&[leaves[1], cd], &root, unknown));
+        //a weight leaf for an allowed mint is not an allowlist leaf
+        let weight_leaf = merkle::weight_leaf(&mints[0], 10_000);
+        assert!(!merkle::verify_proof(&[leaves[1], cd], &root, weight_leaf));
+    }
+
+    #[test]
+    pub fn test_penalty_calculation() {
+        assert_eq!(calculate_penalty(1000, 0), 0);
+        assert_eq!(calculate_penalty(1000, 2_500), 250);
+        assert_eq!(calculate_penalty(1000, BASE_MULTIPLIER as u16), 1000);
+
+        //partial tokens are never forfeited
+        assert_eq!(calculate_penalty(3, 5_000), 1);
+        assert_eq!(
+            calculate_penalty(u64::MAX, BASE_MULTIPLIER as u16),
+            u64::MAX
+        );
+    }
+
+    #[test]
+    pub fn test_unstaked_reward_settlement() {
+        //a stake account with two nfts at 1x and 3x earned 400, plus 50 pending
+        let earned = calculate_reward(100, 0, 40_000).unwrap() + 50;
+        assert_eq!(earned, 450);
+
+        //the unbonding nft's share is held and the rest is paid out
+        let (mut held, mut lifetime) = (0, 0);
+        let start = Unstake::StartUnbonding { weight: 30_000 };
+        assert_eq!(
+            settle_unstaked_reward(start, earned, 100, 0, 0, &mut held, &mut lifetime).unwrap(),
+            150
+        );
+        assert_eq!((held, lifetime), (300, 300));
+
+        //withdrawing early forfeits a share of what the nft earned
+        let withdraw = Unstake::Withdraw { penalty_bps: 5_000 };
+        assert_eq!(
+            settle_unstaked_reward(withdraw, 40, 100, 100, 0, &mut held, &mut lifetime).unwrap(),
+            40 + 300 - 150
+        );
+        assert_eq!((held, lifetime), (0, 0));
+
+        //and withdrawing after unbonding pays out everything
+        let (mut held, mut lifetime) = (300, 300);
+        let withdraw = Unstake::Withdraw { penalty_bps: 0 };
+        assert_eq!(
+            settle_unstaked_reward(withdraw, 0, 100, 100, 0, &mut held, &mut lifetime).unwrap(),
+            300
+        );
+    }
+
+    #[test]
+    pub fn test_claim_before_early_withdrawal() {
+        //an nft at 1x staked at 20 whose stake account claimed everything at 100
+        let (mut held, mut lifetime) = (0, 0);
+        let start = Unstake::StartUnbonding { weight: 10_000 };
+        assert_eq!(
+            settle_unstaked_reward(start, 0, 100, 100, 20, &mut held, &mut lifetime).unwrap(),
+            0
+        );
+
+        //holds nothing, but still earned 80 since it was staked
+        assert_eq!((held, lifetime), (0, 80));
+
+        //so withdrawing early can't skip the cooldown for free
+        let withdraw = Unstake::Withdraw { penalty_bps: 5_000 };
+        let result = settle_unstaked_reward(withdraw, 0, 100, 100, 20, &mut held, &mut lifetime);
+        assert_eq!(result.unwrap_err(), StakingError::PenaltyNotCovered.into());
+        assert_eq!((held, lifetime), (0, 80));
+
+        //and the penalty comes out of what the stake account is owed otherwise
+        assert_eq!(
+            settle_unstaked_reward(withdraw, 60, 120, 100, 20, &mut held, &mut lifetime).unwrap(),
+            60 - 40
+        );
+        assert_eq!((held, lifetime), (0, 0));
+    }
+
+    #[test]
+    pub fn test_capped_reward_calculation() {
+        //uncapped rewarders pay out everything
+        assert_eq!(calculate_capped_reward(100, 0, u64::MAX), 100);
+
+        //the final payout is truncated to what is left of the cap
+        assert_eq!(calculate_capped_reward(100, 1000, 500), 100);
+        assert_eq!(calculate_capped_reward(100, 1000, 950), 50);
+
+        //nothing more is paid once the cap is reached, even if it was lowered
+        assert_eq!(calculate_capped_reward(100, 1000, 1000), 0);
+        assert_eq!(calculate_capped_reward(100, 1000, 2000), 0);
+    }
+
+    #[test]
+    pub fn test_runway_calculation() {
+        let base = BASE_MULTIPLIER as u64;
+
+        //nothing staked or no rate means the vault is never drawn down
+        assert_eq!(calculate_runway(1000, 10, 0), None);
+        assert_eq!(calculate_runway(1000, 0, 5 * base), None);
+
+        assert_eq!(calculate_runway(1000, 10, 5 * base), Some(20));
+        assert_eq!(calculate_runway(999, 10, 5 * base), Some(19));
+        assert_eq!(calculate_runway(0, 10, 5 * base), Some(0));
+
+        //multipliers draw the vault down faster
+        assert_eq!(calculate_runway(1000, 10, 10 * base), Some(10));
+
+        //an emission rate larger than any balance drains the vault immediately
+        assert_eq!(calculate_runway(u64::MAX, u64::MAX, u64::MAX), Some(0));
+    }
+
+    fn creator(address: Pubkey, verified: bool, share: u8) -> Creator {
+        Creator {
+            address,
+            verified,
+            share,
+        }
+    }
+
+    #[test]
+    pub fn test_creator_matching() {
+        let (a, b, c) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let known = vec![
+            CreatorStruct {
+                address: a,
+                verified: true,
+                share: 0,
+            },
+            CreatorStruct {
+                address: b,
+                verified: false,
+                share: 100,
+            },
+        ];
+        let exact = [creator(a, true, 0), creator(b, false, 100)];
+        let partial = [creator(a, true, 0), creator(c, false, 100)];
+        let invalid: ProgramError = StakingError::InvalidMetadataCreators.into();
+        let unverified: ProgramError = StakingError::UnverifiedMetadataCreators.into();
+
+        let mut policy = MetadataPolicy::default();
+        assert!(check_creators(Some(&exact), &known, &policy).is_ok());
+        assert_eq!(
+            check_creators(Some(&partial), &known, &policy),
+            Err(invalid.clone())
+        );
+        assert_eq!(check_creators(None, &known, &policy), Err(invalid.clone()));
+        policy.verified_creators_only = true;
+        assert_eq!(
+            check_creators(Some(&exact), &known, &policy),
+            Err(unverified.clone())
+        );
+
+        policy.creator_match = CreatorMatch::Any;
+        policy.verified_creators_only = false;
+        assert!(check_creators(Some(&partial), &known, &policy).is_ok());
+        assert_eq!(
+            check_creators(Some(&[creator(c, true, 100)]), &known, &policy),
+            Err(invalid.clone())
+        );
+        policy.verified_creators_only = true;
+        assert!(check_creators(Some(&partial), &known, &policy).is_ok());
+        assert_eq!(
+            check_creators(Some(&[creator(b, false, 100)]), &known, &policy),
+            Err(unverified.clone())
+        );
+
+        //shares, the rewarder's verified flags and extra creators are ignored
+        policy.creator_match = CreatorMatch::AnyVerified;
+        policy.verified_creators_only = false;
+        let royalty_split = [creator(c, false, 50), creator(b, true, 50)];
+        assert!(check_creators(Some(&royalty_split), &known, &policy).is_ok());
+        assert_eq!(
+            check_creators(
+                Some(&[creator(a, false, 0), creator(c, true, 100)]),
+                &known,
+                &policy
+            ),
+            Err(unverified.clone())
+        );
+        assert_eq!(
+            check_creators(Some(&[creator(c, true, 100)]), &known, &policy),
+            Err(invalid.clone())
+        );
+    }
+
+    #[test]
+    pub fn test_update_authority_check() {
+        let allowed = Pubkey::new_unique();
+        let additional = Pubkey::new_unique();
+        let rewarder = NftStakeRewarder {
+            allowed_update_authority: allowed,
+            additional_update_authorities: vec![additional],
+            ..Default::default()
+        };
+
+        assert!(check_update_authority(&allowed, &rewarder).is_ok());
+        assert!(check_update_authority(&additional, &rewarder).is_ok());
+        assert_eq!(
+            check_update_authority(&Pubkey::new_unique(), &rewarder),
+            Err(StakingError::InvalidMetadataUpdateAuthority.into())
+        );
+    }
+
+    #[test]
+    pub fn test_edition_check() {
+        use metaplex_token_metadata::state::Key as MetaplexKey;
+
+        let edition_key = Pubkey::new_unique();
+        let metaplex_id = anchor_metaplex::ID;
+        let mut lamports = 0;
+        let mut data = vec![MetaplexKey::MasterEditionV2 as u8, 0, 0];
+        let edition = AccountInfo::new(
+            &edition_key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &metaplex_id,
+            false,
+            0,
+        );
+
+        assert!(check_edition(&edition, 0, COption::Some(edition_key)).is_ok());
+        assert!(check_edition(&edition, 0, COption::None).is_ok());
+        assert_eq!(
+            check_edition(&edition, 6, COption::None),
+            Err(StakingError::InvalidNFTMintDecimals.into())
+        );
+        assert_eq!(
+            check_edition(&edition, 0, COption::Some(Pubkey::new_unique())),
+            Err(StakingError::InvalidNFTMintAuthority.into())
+        );
+
+        //metadata accounts and accounts owned by other programs aren't editions
+        edition.data.borrow_mut()[0] = MetaplexKey::MetadataV1 as u8;
+        assert_eq!(
+            check_edition(&edition, 0, COption::None),
+            Err(StakingError::InvalidNFTEditionAccount.into())
+        );
+        edition.data.borrow_mut()[0] = MetaplexKey::EditionV1 as u8;
+        assert!(check_edition(&edition, 0, COption::None).is_ok());
+
+        let mut lamports = 0;
+        let mut data = vec![MetaplexKey::MasterEditionV2 as u8];
+        let spoofed = AccountInfo::new(
+            &edition_key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &edition_key,
+            false,
+            0,
+        );
+        assert_eq!(
+            check_edition(&spoofed, 0, COption::None),
+            Err(StakingError::InvalidNFTEditionAccount.into())
+        );
+    }
+
+    #[test]
+    pub fn test_stream_payability() {
+        use anchor_lang::solana_program::program_pack::Pack;
+        use spl_token::state::{Account as SplAccount, AccountState, Mint as SplMint};
+
+        let owner = Pubkey::new_unique();
+        let reward_authority = Pubkey::new_unique();
+        let mut stream = RewardStream {
+            reward_mint: Pubkey::new_unique(),
+            reward_mode: RewardMode::Mint,
+            ..Default::default()
+        };
+        let spl_mint = SplMint {
+            mint_authority: COption::Some(reward_authority),
+            is_initialized: true,
+            ..Default::default()
+        };
+        let spl_account = SplAccount {
+            mint: stream.reward_mint,
+            owner,
+            state: AccountState::Initialized,
+            ..Default::default()
+        };
+
+        let (mint_key, vault_key, account_key) = (
+            stream.reward_mint,
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let (mut mint_lamports, mut vault_lamports, mut account_lamports) = (0, 0, 0);
+        let mut mint_data = vec![0; SplMint::LEN];
+        let mut vault_data = vec![0; SplAccount::LEN];
+        let mut account_data = vec![0; SplAccount::LEN];
+        SplMint::pack(spl_mint, &mut mint_data).unwrap();
+        let spl_vault = SplAccount {
+            owner: reward_authority,
+            ..spl_account
+        };
+        SplAccount::pack(spl_vault, &mut vault_data).unwrap();
+        SplAccount::pack(spl_account, &mut account_data).unwrap();
+        let mint = AccountInfo::new(
+            &mint_key,
+            false,
+            true,
+            &mut mint_lamports,
+            &mut mint_data,
+            &spl_token::ID,
+            false,
+            0,
+        );
+        let vault = AccountInfo::new(
+            &vault_key,
+            false,
+            true,
+            &mut vault_lamports,
+            &mut vault_data,
+            &spl_token::ID,
+            false,
+            0,
+        );
+        let account = AccountInfo::new(
+            &account_key,
+            false,
+            true,
+            &mut account_lamports,
+            &mut account_data,
+            &spl_token::ID,
+            false,
+            0,
+        );
+        let can_pay = |stream: &RewardStream| {
+            can_pay_stream(stream, &mint, &vault, &account, &reward_authority, &owner)
+        };
+
+        assert_eq!(can_pay(&stream), Ok(true));
+
+        //a mint the stream's reward authority can no longer mint keeps rewards pending
+        let revoked = SplMint {
+            mint_authority: COption::None,
+            ..spl_mint
+        };
+        SplMint::pack(revoked, &mut mint.data.borrow_mut()).unwrap();
+        assert_eq!(can_pay(&stream), Ok(false));
+
+        //as does a vault that was frozen
+        stream.reward_mode = RewardMode::Vault;
+        assert_eq!(can_pay(&stream), Ok(true));
+        let frozen = SplAccount {
+            state: AccountState::Frozen,
+            ..spl_vault
+        };
+        SplAccount::pack(frozen, &mut vault.data.borrow_mut()).unwrap();
+        assert_eq!(can_pay(&stream), Ok(false));
+        SplAccount::pack(spl_vault, &mut vault.data.borrow_mut()).unwrap();
+
+        //and an owner token account that was frozen
+        let frozen = SplAccount {
+            state: AccountState::Frozen,
+            ..spl_account
+        };
+        SplAccount::pack(frozen, &mut account.data.borrow_mut()).unwrap();
+        assert_eq!(can_pay(&stream), Ok(false));
+
+        //or that doesn't exist yet
+        account.data.borrow_mut().fill(0);
+        assert_eq!(can_pay(&stream), Ok(false));
+
+        //but a token account of someone else is rejected
+        let stranger = SplAccount {
+            owner: Pubkey::new_unique(),
+            ..spl_account
+        };
+        SplAccount::pack(stranger, &mut account.data.borrow_mut()).unwrap();
+        assert_eq!(
+            can_pay(&stream),
+            Err(StakingError::InvalidRewardStreamAccounts.into())
+        );
+    }
+
+    fn serialized_metadata(trailing: &[u8]) -> Vec<u8> {
+        let metadata = metaplex_token_metadata::state::Metadata {
+            key: metaplex_token_metadata::state::Key::MetadataV1,
+            update_authority: Pubkey::new_unique(),
+            mint: Pubkey::new_unique(),
+            data: metaplex_token_metadata::state::Data {
+                name: "gmoot bag #69".to_string(),
+                symbol: "GMOOT".to_string(),
+                uri: "https://gmoot.io/69.json".to_string(),
+                seller_fee_basis_points: 500,
+                creators: None,
+            },
+            primary_sale_happened: false,
+            is_mutable: true,
+            edition_nonce: Some(255),
+        };
+        let mut data = metadata.try_to_vec().unwrap();
+        data.extend_from_slice(trailing);
+        data.resize(MetadataAccount::LEN, 0);
+        data
+    }
+
+    #[test]
+    pub fn test_metadata_collection() {
+        use anchor_lang::AccountDeserialize;
+        use anchor_metaplex::MetadataCollection;
+
+        //metadata created before collections existed is zero padded
+        let data = serialized_metadata(&[]);
+        let metadata = MetadataAccount::try_deserialize(&mut data.as_slice()).unwrap();
+        assert_eq!(metadata.data.name, "gmoot bag #69");
+        assert_eq!(metadata.collection, None);
+
+        //token standard followed by a verified collection
+        let collection_mint = Pubkey::new_unique();
+        let mut trailing = vec![1, 0, 1, 1];
+        trailing.extend_from_slice(collection_mint.as_ref());
+        let data = serialized_metadata(&trailing);
+        let metadata = MetadataAccount::try_deserialize(&mut data.as_slice()).unwrap();
+        assert_eq!(
+            metadata.collection,
+            Some(MetadataCollection {
+                verified: true,
+                key: collection_mint,
+            })
+        );
+
+        //no token standard and an unverified collection
+        let mut trailing = vec![0, 1, 0];
+        trailing.extend_from_slice(collection_mint.as_ref());
+        let data = serialized_metadata(&trailing);
+        let metadata = MetadataAccount::try_deserialize(&mut data.as_slice()).unwrap();
+        assert_eq!(
+            metadata.collection,
+            Some(MetadataCollection {
+                verified: false,
+                key: collection_mint,
+            })
+        );
+    }
+
+    #[test]
+    pub fn test_pool_seed() {
+        let rewarder_address = |pool_seed: &[u8]| {
+            Pubkey::find_program_address(
+                &[b"gmoot", &id().to_bytes(), REWARDER_PREFIX, pool_seed],
+                &id(),
+            )
+            .0
+        };
+
+        //pool 0 keeps the address rewarders had before pools
+        let (legacy, _) =
+            Pubkey::find_program_address(&[b"gmoot", &id().to_bytes(), REWARDER_PREFIX], &id());
+        assert_eq!(rewarder_address(&get_pool_seed(0)), legacy);
+
+        //every other pool gets its own rewarder
+        let pool_one = rewarder_address(&get_pool_seed(1));
+        let pool_two = rewarder_address(&get_pool_seed(2));
+        assert_ne!(pool_one, legacy);
+        assert_ne!(pool_two, legacy);
+        assert_ne!(pool_one, pool_two);
+    }
+
+    #[test]
+    pub fn test_collection_seed() {
+        //short names are used as is so existing rewarders keep their address
+        assert_eq!(get_collection_seed("gmoot"), b"gmoot".to_vec());
+        let max = "a".repeat(MAX_SEED_LEN);
+        assert_eq!(get_collection_seed(&max), max.as_bytes().to_vec());
+
+        //longer names are hashed down to a single seed
+        let long = "The Extraordinarily Long Collection Name";
+        let seed = get_collection_seed(long);
+        assert_eq!(seed.len(), 32);
+        assert_eq!(seed, hash(long.as_bytes()).to_bytes().to_vec());
+        assert_ne!(seed, get_collection_seed(&format!("{}s", long)));
+
+        //the hashed seed can derive a rewarder, the raw name can't
+        assert!(Pubkey::try_find_program_address(
+            &[&seed, &id().to_bytes(), REWARDER_PREFIX],
+            &id()
+        )
+        .is_some());
+        assert!(Pubkey::try_find_program_address(
+            &[long.as_bytes(), &id().to_bytes(), REWARDER_PREFIX],
+            &id()
+        )
+        .is_none());
+    }
+
+    fn serialized_len<T: AccountSerialize>(account: &T) -> usize {
+        let mut data = Vec::new();
+        account.try_serialize(&mut data).unwrap();
+        data.len()
+    }
+
+    #[test]
+    pub fn test_account_len() {
+        //every variable length field at its largest
+        let collection = "a".repeat(64);
+        let rewarder = NftStakeRewarder {
+            creators: vec![CreatorStruct::default(); MAX_CREATOR_LIMIT],
+            collection: collection.clone(),
+            lockup_tiers: vec![LockupTier::default(); MAX_LOCKUP_TIERS],
+            collection_strategy: CollectionStrategy::VerifiedCollection {
+                mint: Pubkey::new_unique(),
+            },
+            additional_update_authorities: vec![
+                Pubkey::new_unique();
+                MAX_ADDITIONAL_UPDATE_AUTHORITIES
+            ],
+            reward_streams: vec![RewardStream::default(); MAX_REWARD_STREAMS],
+            ..Default::default()
+        };
+        assert_eq!(
+            serialized_len(&rewarder),
+            NftStakeRewarder::calculate_len(MAX_CREATOR_LIMIT, &collection)
+        );
+
+        //creators are sized for what the rewarder was created with
+        let rewarder = NftStakeRewarder {
+            creators: vec![CreatorStruct::default(); 1],
+            ..rewarder
+        };
+        assert_eq!(
+            serialized_len(&rewarder),
+            NftStakeRewarder::calculate_len(1, &collection)
+        );
+
+        let stake_account = NftStakeAccount {
+            owner: Pubkey::new_unique(),
+            rewarder: Pubkey::new_unique(),
+            num_staked: u16::MAX,
+            total_weight: u64::MAX,
+            bump: u8::MAX,
+            last_claimed: i64::MAX,
+            reward_per_nft_paid: u128::MAX,
+            pending_reward: u64::MAX,
+            stream_checkpoints: [StreamCheckpoint::default(); MAX_REWARD_STREAMS],
+            version: u8::MAX,
+            v0_staked: u16::MAX,
+            num_blocked: u16::MAX,
+            reserved: Default::default(),
+        };
+        assert_eq!(serialized_len(&stake_account), NftStakeAccount::LEN);
+
+        let receipt = StakeReceipt {
+            stake_account: Pubkey::new_unique(),
+            mint: Pubkey::new_unique(),
+            staked_at: i64::MAX,
+            multiplier: u32::MAX,
+            weight: u64::MAX,
+            lockup_end: i64::MAX,
+            unbonding_end: i64::MAX,
+            bump: u8::MAX,
+            unbonding_reward: u64::MAX,
+            unbonding_stream_rewards: [u64::MAX; MAX_REWARD_STREAMS],
+            blocked: true,
+            reward_per_nft_staked: u128::MAX,
+            stream_rewards_per_nft_staked: [u128::MAX; MAX_REWARD_STREAMS],
+            lifetime_reward: u64::MAX,
+            lifetime_stream_rewards: [u64::MAX; MAX_REWARD_STREAMS],
+        };
+        assert_eq!(serialized_len(&receipt), StakeReceipt::LEN);
+
+        let blocked_mint = BlockedMint {
+            rewarder: Pubkey::new_unique(),
+            mint: Pubkey::new_unique(),
+            blocked_at: i64::MAX,
+            stake_account: Pubkey::new_unique(),
+            bump: u8::MAX,
+        };
+        assert_eq!(serialized_len(&blocked_mint), BlockedMint::LEN);
+    }
+
+    fn v0_account_data<T: anchor_lang::Discriminator>(
+        account: &impl AnchorSerialize,
+        len: usize,
+    ) -> Vec<u8> {
+        let mut data = T::discriminator().to_vec();
+        data.extend(account.try_to_vec().unwrap());
+        //accounts were allocated at least their serialized size
+        assert!(data.len() <= len);
+        data.resize(len, 0);
+        data
+    }
+
+    #[test]
+    pub fn test_migrate_rewarder() {
+        let authority = Pubkey::new_unique();
+        let collection = "gmoot".to_string();
+        let v0 = NftStakeRewarderV0 {
+            authority,
+            reward_mint: Pubkey::new_unique(),
+            reward_authority_bump: 254,
+            reward_rate: 10,
+            allowed_update_authority: Pubkey::new_unique(),
+            creators: vec![CreatorStruct::default(); 2],
+            collection: collection.clone(),
+            enforce_metadata: true,
+            total_staked: 7,
+        };
+
+        //v0 sized rewarders by the in memory size of their fields
+        let len = 32 * 3 + 1 + 8 + 4 + 1 + 34 * 2 + 24 + collection.len();
+        let data = v0_account_data::<NftStakeRewarder>(&v0, len);
+        assert!(NftStakeRewarder::try_deserialize(&mut data.as_slice()).is_err());
+
+        let rewarder = upgrade_v0_rewarder(&data, 1_000).unwrap();
+        assert_eq!(rewarder.authority, authority);
+        assert_eq!(rewarder.reward_authority_bump, 254);
+        assert_eq!(rewarder.reward_rate, 10);
+        assert_eq!(rewarder.creators.len(), 2);
+        assert_eq!(rewarder.collection, collection);
+        assert!(rewarder.enforce_metadata);
+        assert_eq!(rewarder.total_staked, 7);
+        assert_eq!(rewarder.total_weight, 7 * BASE_MULTIPLIER as u64);
+        assert_eq!(rewarder.accrued_reward_per_nft, 10 * 1_000);
+        assert_eq!(rewarder.last_accrued, 1_000);
+        assert_eq!(rewarder.reward_mode, RewardMode::Mint);
+        assert_eq!(rewarder.custody_mode, CustodyMode::TransferAuthority);
+        assert_eq!(rewarder.lockup_tiers.len(), 1);
+        assert_eq!(rewarder.pool_id, 0);
+        assert_eq!(rewarder.version, REWARDER_VERSION);
+        assert_eq!(rewarder.v0_reward_rate, 10);
+
+        //the migrated rewarder reads back as the current layout
+        let mut migrated = vec![0; NftStakeRewarder::calculate_len(2, &collection)];
+        rewarder
+            .try_serialize(&mut migrated.as_mut_slice())
+            .unwrap();
+        let rewarder = NftStakeRewarder::try_deserialize(&mut migrated.as_slice()).unwrap();
+        assert_eq!(rewarder.v0_reward_rate, 10);
+
+        //anything else isn't a v0 rewarder
+        let data = v0_account_data::<NftStakeAccount>(&v0, len);
+        assert!(upgrade_v0_rewarder(&data, 1_000).is_err());
+    }
+
+    #[test]
+    pub fn test_migrate_stake_account() {
+        let rewarder_key = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let v0 = NftStakeAccountV0 {
+            owner,
+            rewarder: rewarder_key,
+            num_staked: 2,
+            bump: 255,
+            last_claimed: 400,
+        };
+
+        //v0 sized stake accounts by the in memory size of their fields
+        let data = v0_account_data::<NftStakeAccount>(&v0, 88);
+        assert!(NftStakeAccount::try_deserialize(&mut data.as_slice()).is_err());
+
+        let mut rewarder = NftStakeRewarder {
+            reward_rate: 10,
+            ..Default::default()
+        };
+        rewarder.accrued_reward_per_nft = 10 * 1_000;
+        rewarder.last_accrued = 1_000;
+        rewarder.v0_reward_rate = 10;
+
+        let stake_account = upgrade_v0_stake_account(&data, &rewarder).unwrap();
+        assert_eq!(stake_account.owner, owner);
+        assert_eq!(stake_account.rewarder, rewarder_key);
+        assert_eq!(stake_account.num_staked, 2);
+        assert_eq!(stake_account.total_weight, 2 * BASE_MULTIPLIER as u64);
+        assert_eq!(stake_account.bump, 255);
+        assert_eq!(stake_account.last_claimed, 400);
+        assert_eq!(stake_account.version, STAKE_ACCOUNT_VERSION);
+        assert_eq!(stake_account.v0_staked, 2);
+
+        //it has earned what v0 would have paid out since it last claimed
+        let earned = |rewarder: &NftStakeRewarder| {
+            calculate_reward(
+                rewarder.accrued_reward_per_nft,
+                stake_account.reward_per_nft_paid,
+                stake_account.total_weight,
+            )
+            .unwrap()
+        };
+        assert_eq!(earned(&rewarder), 10 * (1_000 - 400) * 2);
+
+        //and earns at the current rate from then on
+        rewarder.reward_rate = 20;
+        update_accrued_reward(&mut rewarder, 1_100).unwrap();
+        assert_eq!(earned(&rewarder), 10 * (1_000 - 400) * 2 + 20 * 100 * 2);
+
+        let mut migrated = vec![0; NftStakeAccount::LEN];
+        stake_account
+            .try_serialize(&mut migrated.as_mut_slice())
+            .unwrap();
+        let stake_account = NftStakeAccount::try_deserialize(&mut migrated.as_slice()).unwrap();
+        assert_eq!(stake_account.v0_staked, 2);
+    }
+
+    #[test]
+    pub fn test_resize_account_data() {
+        //lay the account out like the runtime does, length first then the data and
+        //the room it is allowed to grow into
+        let mut buffer = vec![0u8; 8 + 4 + MAX_PERMITTED_DATA_INCREASE];
+        buffer[..8].copy_from_slice(&4u64.to_le_bytes());
+        buffer[8..12].copy_from_slice(&[1, 2, 3, 4]);
+
+        let key = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let mut lamports = 0;
+        let (len, data) = buffer.split_at_mut(8);
+        let account = AccountInfo::new(
+            &key,
+            false,
+            true,
+            &mut lamports,
+            &mut data[..4],
+            &owner,
+            false,
+            0,
+        );
+
+        resize_account_data(&account, 36).unwrap();
+        assert_eq!(account.data_len(), 36);
+        assert_eq!(account.data.borrow()[..4], [1, 2, 3, 4]);
+        assert!(account.data.borrow()[4..].iter().all(|byte| *byte == 0));
+
+        let too_large = 36 + MAX_PERMITTED_DATA_INCREASE + 1;
+        assert!(resize_account_data(&account, too_large).is_err());
+        drop(account);
+
+        assert_eq!(u64::from_le_bytes(<[u8; 8]>::try_from(&*len).unwrap()), 36);
+    }
+
+    proptest! {
+        #[test]
+        fn prop_reward_matches_wide_math(
+            accrued in any::<u128>(),
+            paid in any::<u128>(),
+            total_weight in any::<u64>(),
+        ) {
+            let expected = if accrued <= paid {
+                Some(0)
+            } else {
+                (accrued - paid)
+                    .checked_mul(total_weight as u128)
+                    .map(|reward| reward / BASE_MULTIPLIER as u128)
+                    .and_then(|reward| u64::try_from(reward).ok())
+            };
+
+            let result = calculate_reward(accrued, paid, total_weight);
+            prop_assert_eq!(result.ok(), expected);
+        }
+
+        #[test]
+        fn prop_accrual_is_additive(
+            reward_rate in any::<u64>(),
+            start in any::<i32>(),
+            first in 0..u32::MAX as i64,
+            second in 0..u32::MAX as i64,
+        ) {
+            let start = start as i64;
+            let mut rewarder = NftStakeRewarder {
+                reward_rate,
+                last_accrued: start,
+                ..Default::default()
+            };
+
+            //accruing in two steps earns the same as accruing once
+            update_accrued_reward(&mut rewarder, start + first).unwrap();
+            update_accrued_reward(&mut rewarder, start + first + second).unwrap();
+
+            let expected = reward_rate as u128 * (first + second) as u128;
+            prop_assert_eq!(rewarder.accrued_reward_per_nft, expected);
+            prop_assert_eq!(rewarder.last_accrued, start + first + second);
+        }
     }
 }
@@ -0,0 +1,32 @@
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::keccak;
+
+/// Returns the leaf for a mint and its reward weight in basis points,
+/// `keccak256(mint || weight_le_bytes)`.
+pub fn weight_leaf(mint: &Pubkey, weight: u32) -> [u8; 32] {
+    keccak::hashv(&[mint.as_ref(), &weight.to_le_bytes()]).0
+}
+
+/// Returns the leaf for a mint in an allowlist, `keccak256(mint)`.
+pub fn mint_leaf(mint: &Pubkey) -> [u8; 32] {
+    keccak::hash(mint.as_ref()).0
+}
+
+/// Returns the parent of two nodes. The pair is sorted before hashing so that
+/// proofs don't need to record which side each sibling is on.
+pub fn hash_pair(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    if a <= b {
+        keccak::hashv(&[a, b]).0
+    } else {
+        keccak::hashv(&[b, a]).0
+    }
+}
+
+/// Checks that `leaf` is part of the tree with the given `root`.
+pub fn verify_proof(proof: &[[u8; 32]], root: &[u8; 32], leaf: [u8; 32]) -> bool {
+    let computed = proof
+        .iter()
+        .fold(leaf, |node, sibling| hash_pair(&node, sibling));
+
+    &computed == root
+}
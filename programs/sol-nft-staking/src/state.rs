@@ -1,20 +1,17 @@
-use std::mem::size_of;
-
 use anchor_lang::prelude::*;
 use metaplex_token_metadata::state::Creator;
 
+/// The space an account takes up on chain, its Borsh serialized size plus the
+/// 8 byte discriminator.
 pub trait Len {
     const LEN: usize;
 }
 
-impl<T> Len for T
-where
-    T: AnchorDeserialize + AnchorSerialize,
-{
-    const LEN: usize = 8 + size_of::<T>();
-}
+/// the size of the length prefix Borsh writes before strings and vecs
+pub const BORSH_LEN_PREFIX: usize = 4;
 
 #[account]
+#[derive(Default)]
 pub struct NftStakeRewarder {
     pub authority: Pubkey,
     pub reward_mint: Pubkey,
@@ -32,30 +29,299 @@ pub struct NftStakeRewarder {
     pub enforce_metadata: bool,
     /// The total number of NFTs staked with this rewarder.
     pub total_staked: u32,
+    /// the total reward earned by a single NFT staked since the rewarder was created
+    pub accrued_reward_per_nft: u128,
+    /// the unix timestamp that `accrued_reward_per_nft` was last brought up to date
+    pub last_accrued: i64,
+    /// how rewards are paid out to stakers
+    pub reward_mode: RewardMode,
+    /// how staked NFTs are held while they are staked
+    pub custody_mode: CustodyMode,
+    /// the combined reward weight of every NFT staked with this rewarder
+    pub total_weight: u64,
+    /// the lockup periods stakers can choose from when staking an NFT
+    pub lockup_tiers: Vec<LockupTier>,
+    /// the number of seconds an unstaked NFT waits before it can be withdrawn
+    pub unbonding_period: i64,
+    /// the share of an NFT's lifetime rewards in basis points forfeited by
+    /// withdrawing it before its unbonding period ends. Early withdrawals aren't
+    /// allowed when 0
+    pub early_unstake_penalty_bps: u16,
+    /// the Merkle root of (mint, weight) pairs for NFTs that earn at a weight other
+    /// than 1x. All NFTs earn at 1x while the root is all zeros
+    pub weights_root: [u8; 32],
+    /// how staked NFTs are checked to belong to the collection
+    pub collection_strategy: CollectionStrategy,
+    /// which metadata checks are enforced on staked NFTs
+    pub metadata_policy: MetadataPolicy,
+    /// update authorities allowed alongside `allowed_update_authority`
+    pub additional_update_authorities: Vec<Pubkey>,
+    /// the Merkle root of the mints allowed to be staked. Any mint that passes the
+    /// metadata checks can be staked while the root is all zeros
+    pub allowlist_root: [u8; 32],
+    /// the key proposed to become the new authority. Has to accept before the
+    /// transfer takes effect. No transfer is pending while it is the default pubkey
+    pub pending_authority: Pubkey,
+    /// the parts of the rewarder that are paused by the authority
+    pub paused: PauseFlags,
+    /// when rewards are emitted and how the reward rate decays over time
+    pub schedule: EmissionSchedule,
+    /// the most reward tokens the rewarder will ever pay out. Uncapped when 0
+    pub emission_cap: u64,
+    /// the reward tokens paid out by the rewarder so far
+    pub total_emitted: u64,
+    /// extra tokens earned alongside the reward mint
+    pub reward_streams: Vec<RewardStream>,
+    /// identifies the rewarder among the pools for its collection
+    pub pool_id: u64,
+    /// the layout the rewarder was written with, see `REWARDER_VERSION`
+    pub version: u8,
+    /// the reward rate v0 stake accounts earned at, fixed when the rewarder was
+    /// migrated from v0. 0 for rewarders created since
+    pub v0_reward_rate: u64,
+    /// space set aside for future fields, always zero
+    pub reserved: [u8; 23],
+}
+
+/// The current layout of rewarders, upgraded to by `migrate_rewarder`.
+pub const REWARDER_VERSION: u8 = 1;
+
+/// The rewarder layout deployed before accounts were versioned, read by
+/// `migrate_rewarder`.
+#[derive(AnchorDeserialize, AnchorSerialize)]
+pub struct NftStakeRewarderV0 {
+    pub authority: Pubkey,
+    pub reward_mint: Pubkey,
+    pub reward_authority_bump: u8,
+    pub reward_rate: u64,
+    pub allowed_update_authority: Pubkey,
+    pub creators: Vec<CreatorStruct>,
+    pub collection: String,
+    pub enforce_metadata: bool,
+    pub total_staked: u32,
 }
 
 impl NftStakeRewarder {
     pub fn calculate_len(num_creators: usize, collection: &str) -> usize {
-        let mut size = size_of::<Pubkey>() * 3; //stored pubkeys
+        let mut size = 8; //discriminator
+        size += 32 * 3; //stored pubkeys
         size += 1; // authority bump
         size += 8; // reward rate
-        size += 4; //total staked
+        size += BORSH_LEN_PREFIX + CreatorStruct::LEN * num_creators; //creators
+        size += BORSH_LEN_PREFIX + collection.len(); //collection
         size += 1; //enforced metadata
-
-        let creator_size = size_of::<CreatorStruct>() * num_creators;
-        size += creator_size;
-        let collection_size = size_of::<String>() + collection.len();
-        size += collection_size;
+        size += 4; //total staked
+        size += 16; //accrued reward per nft
+        size += 8; //last accrued
+        size += 1; //reward mode
+        size += 1; //custody mode
+        size += 8; //total weight
+        size += BORSH_LEN_PREFIX + LockupTier::LEN * MAX_LOCKUP_TIERS; //lockup tiers
+        size += 8; //unbonding period
+        size += 2; //early unstake penalty
+        size += 32; //weights root
+        size += CollectionStrategy::LEN; //collection strategy
+        size += MetadataPolicy::LEN; //metadata policy
+        size += BORSH_LEN_PREFIX + 32 * MAX_ADDITIONAL_UPDATE_AUTHORITIES; //additional update authorities
+        size += 32; //allowlist root
+        size += 32; //pending authority
+        size += PauseFlags::LEN; //pause flags
+        size += EmissionSchedule::LEN; //emission schedule
+        size += 8; //emission cap
+        size += 8; //total emitted
+        size += BORSH_LEN_PREFIX + RewardStream::LEN * MAX_REWARD_STREAMS; //reward streams
+        size += 8; //pool id
+        size += 1; //version
+        size += 8; //v0 reward rate
+        size += 23; //reserved
 
         size
     }
 }
 
+#[derive(Debug, AnchorDeserialize, AnchorSerialize, Clone, Copy, PartialEq)]
+pub enum RewardMode {
+    /// rewards are minted on demand. The reward authority must be the mint authority
+    Mint,
+    /// rewards are transferred out of a pre-funded vault owned by the reward authority
+    Vault,
+}
+
+#[allow(clippy::derivable_impls)]
+impl Default for RewardMode {
+    fn default() -> Self {
+        RewardMode::Mint
+    }
+}
+
+#[derive(Debug, AnchorDeserialize, AnchorSerialize, Clone, Copy, PartialEq)]
+pub enum CustodyMode {
+    /// ownership of the owner's NFT token account is transferred to the stake account
+    TransferAuthority,
+    /// the NFT is transferred into a token account owned by the stake account
+    Escrow,
+    /// the NFT stays in the owner's token account, which is delegated to the
+    /// stake account and frozen through the Metaplex edition
+    Freeze,
+}
+
+#[allow(clippy::derivable_impls)]
+impl Default for CustodyMode {
+    fn default() -> Self {
+        CustodyMode::TransferAuthority
+    }
+}
+
+#[derive(Debug, AnchorDeserialize, AnchorSerialize, Clone, Copy, PartialEq)]
+pub enum CollectionStrategy {
+    /// the NFT's name must start with the rewarder's `collection`
+    NamePrefix,
+    /// the NFT must be a verified member of the Metaplex collection with this mint
+    VerifiedCollection { mint: Pubkey },
+}
+
+#[allow(clippy::derivable_impls)]
+impl Default for CollectionStrategy {
+    fn default() -> Self {
+        CollectionStrategy::NamePrefix
+    }
+}
+
+impl CollectionStrategy {
+    /// the serialized size of the largest variant
+    pub const LEN: usize = 1 + 32;
+}
+
+/// the maximum number of update authorities a rewarder can allow alongside
+/// `allowed_update_authority`
+pub const MAX_ADDITIONAL_UPDATE_AUTHORITIES: usize = 3;
+
+#[derive(Debug, AnchorDeserialize, AnchorSerialize, Clone, Copy, PartialEq)]
+pub enum CreatorMatch {
+    /// the NFT's creators must be exactly the rewarder's creators
+    All,
+    /// at least one of the NFT's creators must be one of the rewarder's creators
+    Any,
+    /// at least one of the rewarder's creator addresses must be a verified creator
+    /// of the NFT. Shares and any other creators are ignored
+    AnyVerified,
+}
+
+#[allow(clippy::derivable_impls)]
+impl Default for CreatorMatch {
+    fn default() -> Self {
+        CreatorMatch::All
+    }
+}
+
+/// The metadata checks enforced on staked NFTs when `enforce_metadata` is set.
+/// The metadata account is always required to be the PDA for the NFT mint.
+#[derive(Debug, AnchorDeserialize, AnchorSerialize, Clone, Copy, PartialEq)]
+pub struct MetadataPolicy {
+    /// the update authority must be one of the rewarder's allowed update authorities
+    pub check_update_authority: bool,
+    /// the NFT must belong to the collection according to the collection strategy
+    pub check_collection: bool,
+    /// the NFT's creators must match the rewarder's creators
+    pub check_creators: bool,
+    /// how the NFT's creators are matched against the rewarder's creators
+    pub creator_match: CreatorMatch,
+    /// only verified creators count towards a match
+    pub verified_creators_only: bool,
+}
+
+impl MetadataPolicy {
+    pub const LEN: usize = 1 + 1 + 1 + 1 + 1;
+}
+
+impl Default for MetadataPolicy {
+    fn default() -> Self {
+        MetadataPolicy {
+            check_update_authority: true,
+            check_collection: true,
+            check_creators: true,
+            creator_match: CreatorMatch::All,
+            verified_creators_only: false,
+        }
+    }
+}
+
+/// The parts of a rewarder that can be paused in an emergency. Unstaking is
+/// always allowed so that stakers can retrieve their NFTs.
+#[derive(Debug, AnchorDeserialize, AnchorSerialize, Default, Clone, Copy, PartialEq)]
+pub struct PauseFlags {
+    /// new NFTs can't be staked
+    pub staking: bool,
+    /// rewards can't be claimed. Rewards earned by unstaking are kept as pending
+    pub claiming: bool,
+    /// staked NFTs earn no rewards
+    pub accrual: bool,
+}
+
+impl PauseFlags {
+    pub const LEN: usize = 1 + 1 + 1;
+}
+
+/// When a rewarder emits rewards. The default schedule emits at the full reward
+/// rate forever.
+#[derive(Debug, AnchorDeserialize, AnchorSerialize, Default, Clone, Copy, PartialEq)]
+pub struct EmissionSchedule {
+    /// the unix timestamp rewards start accruing from. No start time when 0
+    pub start_time: i64,
+    /// the unix timestamp rewards stop accruing at. No end time when 0
+    pub end_time: i64,
+    /// the number of seconds after which the reward rate halves, counted from
+    /// `start_time`. The rate never halves when 0
+    pub halving_interval: i64,
+}
+
+impl EmissionSchedule {
+    pub const LEN: usize = 8 + 8 + 8;
+}
+
+/// An extra token earned by stakers alongside the rewarder's reward mint.
+#[derive(Debug, AnchorDeserialize, AnchorSerialize, Default, Clone, Copy, PartialEq)]
+pub struct RewardStream {
+    /// the mint of the reward token
+    pub reward_mint: Pubkey,
+    /// the PDA bump of the stream's reward authority
+    pub reward_authority_bump: u8,
+    /// tokens rewarded per staked NFT per second
+    pub reward_rate: u64,
+    /// how the stream's rewards are paid out
+    pub reward_mode: RewardMode,
+    /// the total reward earned by a single NFT staked since the stream was added
+    pub accrued_reward_per_nft: u128,
+}
+
+impl RewardStream {
+    pub const LEN: usize = 32 + 1 + 8 + 1 + 16;
+}
+
+/// the maximum number of lockup tiers a rewarder can offer
+pub const MAX_LOCKUP_TIERS: usize = 4;
+
+#[derive(Debug, AnchorDeserialize, AnchorSerialize, Default, Clone, Copy, PartialEq)]
+pub struct LockupTier {
+    /// the number of seconds an NFT staked in this tier can't be unstaked for
+    pub duration: i64,
+    /// the multiplier applied to the rewards earned in this tier in basis points
+    pub multiplier: u32,
+}
+
+impl LockupTier {
+    pub const LEN: usize = 8 + 4;
+}
+
 #[derive(Debug, AnchorDeserialize, AnchorSerialize, Default, Clone)]
 pub struct CreatorStruct {
-    address: Pubkey,
-    verified: bool,
-    share: u8,
+    pub address: Pubkey,
+    pub verified: bool,
+    pub share: u8,
+}
+
+impl CreatorStruct {
+    pub const LEN: usize = 32 + 1 + 1;
 }
 
 impl PartialEq<Creator> for &CreatorStruct {
@@ -71,6 +337,179 @@ pub struct NftStakeAccount {
     pub owner: Pubkey,
     pub rewarder: Pubkey,
     pub num_staked: u16,
+    /// the combined reward weight of the staked NFTs in basis points, an NFT
+    /// staked with a 1x multiplier has a weight of 10,000
+    pub total_weight: u64,
     pub bump: u8,
     pub last_claimed: i64,
+    /// the rewarder's `accrued_reward_per_nft` at the last time rewards were paid out
+    pub reward_per_nft_paid: u128,
+    /// rewards earned but not yet paid out because the reward vault ran dry
+    pub pending_reward: u64,
+    /// the checkpoints for each of the rewarder's reward streams, in the same order
+    pub stream_checkpoints: [StreamCheckpoint; 3],
+    /// the layout the stake account was written with, see `STAKE_ACCOUNT_VERSION`
+    pub version: u8,
+    /// the NFTs staked before stake receipts existed that don't have a receipt
+    /// yet, see `migrate_staked_nft`
+    pub v0_staked: u16,
+    /// the staked NFTs that are blocked by the rewarder authority. Rewards can't
+    /// be claimed while any are
+    pub num_blocked: u16,
+    /// space set aside for future fields, always zero
+    pub reserved: [u8; 19],
+}
+
+/// The current layout of stake accounts, upgraded to by `migrate_stake_account`.
+pub const STAKE_ACCOUNT_VERSION: u8 = 1;
+
+/// The stake account layout deployed before accounts were versioned, read by
+/// `migrate_stake_account`.
+#[derive(AnchorDeserialize, AnchorSerialize)]
+pub struct NftStakeAccountV0 {
+    pub owner: Pubkey,
+    pub rewarder: Pubkey,
+    pub num_staked: u16,
+    pub bump: u8,
+    pub last_claimed: i64,
+}
+
+impl Len for NftStakeAccount {
+    const LEN: usize = 8 //discriminator
+        + 32 //owner
+        + 32 //rewarder
+        + 2 //num staked
+        + 8 //total weight
+        + 1 //bump
+        + 8 //last claimed
+        + 16 //reward per nft paid
+        + 8 //pending reward
+        + StreamCheckpoint::LEN * MAX_REWARD_STREAMS //stream checkpoints
+        + 1 //version
+        + 2 //v0 staked
+        + 2 //num blocked
+        + 19; //reserved
+}
+
+/// the maximum number of extra reward streams a rewarder can have
+pub const MAX_REWARD_STREAMS: usize = 3;
+
+//anchor's IDL parser only understands literal array lengths, so the length of
+//`stream_checkpoints` and the receipt's per stream arrays is spelled out. This
+//stops compiling if it drifts from MAX_REWARD_STREAMS
+#[allow(dead_code)]
+fn stream_checkpoints(account: &NftStakeAccount) -> [StreamCheckpoint; MAX_REWARD_STREAMS] {
+    account.stream_checkpoints
+}
+
+#[allow(dead_code)]
+fn unbonding_stream_rewards(receipt: &StakeReceipt) -> [u64; MAX_REWARD_STREAMS] {
+    receipt.unbonding_stream_rewards
+}
+
+#[allow(dead_code)]
+fn stream_rewards_per_nft_staked(receipt: &StakeReceipt) -> [u128; MAX_REWARD_STREAMS] {
+    receipt.stream_rewards_per_nft_staked
+}
+
+#[allow(dead_code)]
+fn lifetime_stream_rewards(receipt: &StakeReceipt) -> [u64; MAX_REWARD_STREAMS] {
+    receipt.lifetime_stream_rewards
+}
+
+/// A stake account's progress earning a reward stream.
+#[derive(Debug, AnchorDeserialize, AnchorSerialize, Default, Clone, Copy, PartialEq)]
+pub struct StreamCheckpoint {
+    /// the stream's `accrued_reward_per_nft` at the last time rewards were paid out
+    pub reward_per_nft_paid: u128,
+    /// stream rewards earned but not yet paid out
+    pub pending_reward: u64,
+}
+
+impl StreamCheckpoint {
+    pub const LEN: usize = 16 + 8;
+}
+
+/// basis points multiplier that earns exactly the reward rate
+pub const BASE_MULTIPLIER: u32 = 10_000;
+
+#[account]
+pub struct StakeReceipt {
+    /// the stake account the NFT is staked with
+    pub stake_account: Pubkey,
+    /// the mint of the staked NFT
+    pub mint: Pubkey,
+    /// the unix timestamp that the NFT was staked at
+    pub staked_at: i64,
+    /// the multiplier of the NFT's lockup tier in basis points
+    pub multiplier: u32,
+    /// the reward weight of the NFT in basis points, its weight from the rewarder's
+    /// weights root scaled by the lockup multiplier
+    pub weight: u64,
+    /// the unix timestamp before which the NFT can't be unstaked
+    pub lockup_end: i64,
+    /// the unix timestamp the NFT can be withdrawn at once it has been unstaked,
+    /// 0 while the NFT is still staked
+    pub unbonding_end: i64,
+    pub bump: u8,
+    /// the rewards the NFT earned since the stake account was last paid out,
+    /// held from when it is unstaked until it is withdrawn
+    pub unbonding_reward: u64,
+    /// the held rewards of each of the rewarder's reward streams, in the same order
+    pub unbonding_stream_rewards: [u64; 3],
+    /// whether the NFT is blocked by the rewarder authority, which stops it from
+    /// earning until it is unblocked or force unstaked
+    pub blocked: bool,
+    /// the rewarder's `accrued_reward_per_nft` when the NFT was staked
+    pub reward_per_nft_staked: u128,
+    /// each reward stream's `accrued_reward_per_nft` when the NFT was staked, in
+    /// the same order
+    pub stream_rewards_per_nft_staked: [u128; 3],
+    /// the rewards the NFT earned over its whole stake, set when it is unstaked.
+    /// Withdrawing early forfeits a share of them, even if some were claimed
+    pub lifetime_reward: u64,
+    /// the lifetime rewards of each of the rewarder's reward streams, in the same order
+    pub lifetime_stream_rewards: [u64; 3],
+}
+
+impl Len for StakeReceipt {
+    const LEN: usize = 8 //discriminator
+        + 32 //stake account
+        + 32 //mint
+        + 8 //staked at
+        + 4 //multiplier
+        + 8 //weight
+        + 8 //lockup end
+        + 8 //unbonding end
+        + 1 //bump
+        + 8 //unbonding reward
+        + 8 * MAX_REWARD_STREAMS //unbonding stream rewards
+        + 1 //blocked
+        + 16 //reward per nft staked
+        + 16 * MAX_REWARD_STREAMS //stream rewards per nft staked
+        + 8 //lifetime reward
+        + 8 * MAX_REWARD_STREAMS; //lifetime stream rewards
+}
+
+#[account]
+pub struct BlockedMint {
+    /// the rewarder the mint is blocked from
+    pub rewarder: Pubkey,
+    /// the blocked NFT mint
+    pub mint: Pubkey,
+    /// the unix timestamp that the mint was blocked at
+    pub blocked_at: i64,
+    /// the stake account the mint is staked with, the default pubkey when it
+    /// isn't staked
+    pub stake_account: Pubkey,
+    pub bump: u8,
+}
+
+impl Len for BlockedMint {
+    const LEN: usize = 8 //discriminator
+        + 32 //rewarder
+        + 32 //mint
+        + 8 //blocked at
+        + 32 //stake account
+        + 1; //bump
 }
@@ -3,6 +3,7 @@ import { Program } from "@project-serum/anchor";
 import { SolNftStaking } from "../target/types/sol_nft_staking";
 import * as splToken from "@solana/spl-token";
 import { expect } from "chai";
+import { createHash } from "crypto";
 import { programs, actions } from "@metaplex/js";
 import { Metadata } from "@metaplex-foundation/mpl-token-metadata";
 
@@ -16,6 +17,43 @@ describe("sol-nft-staking", () => {
   const systemProgram = anchor.web3.SystemProgram.programId;
   const rentSysvar = anchor.web3.SYSVAR_RENT_PUBKEY;
   const clockSysvar = anchor.web3.SYSVAR_CLOCK_PUBKEY;
+  const tokenMetadataProgram = programs.metadata.MetadataProgram.PUBKEY;
+
+  const getCollectionSeed = (collectionName: string): Buffer => {
+    const name = Buffer.from(collectionName);
+    return name.length > 32
+      ? createHash("sha256").update(name).digest()
+      : name;
+  };
+
+  const getEditionAddress = async (
+    mint: anchor.web3.PublicKey
+  ): Promise<anchor.web3.PublicKey> => {
+    const [edition, _] = await anchor.web3.PublicKey.findProgramAddress(
+      [
+        Buffer.from("metadata"),
+        tokenMetadataProgram.toBuffer(),
+        mint.toBuffer(),
+        Buffer.from("edition"),
+      ],
+      tokenMetadataProgram
+    );
+    return edition;
+  };
+
+  // Expects the transaction to fail with the program error of the given name
+  const expectProgramError = async (tx: Promise<any>, errorName: string) => {
+    const { code } = solNftStakingProgram.idl.errors.find(
+      (error) => error.name === errorName
+    );
+    try {
+      await tx;
+    } catch (err) {
+      expect(err.code).to.equal(code);
+      return;
+    }
+    expect.fail(`expected the transaction to fail with ${errorName}`);
+  };
 
   const mintNFT = async (
     connection: anchor.web3.Connection,
@@ -115,6 +153,24 @@ describe("sol-nft-staking", () => {
     });
     await connection.confirmTransaction(signTx, "confirmed");
 
+    //create the master edition, which takes over the minting authority
+    const masterEditionTx = new programs.metadata.CreateMasterEditionV3(
+      { feePayer: owner.publicKey },
+      {
+        edition: await getEditionAddress(mintkeypair.publicKey),
+        metadata: await Metadata.getPDA(mintkeypair.publicKey),
+        updateAuthority: creator.publicKey,
+        mint: mintkeypair.publicKey,
+        mintAuthority: owner.publicKey,
+        maxSupply: new anchor.BN(0),
+      }
+    );
+    const masterEditionTxSig = await connection.sendTransaction(
+      masterEditionTx,
+      [owner, creator]
+    );
+    await connection.confirmTransaction(masterEditionTxSig, "confirmed");
+
     const nftMint = new splToken.Token(
       connection,
       mintkeypair.publicKey,
@@ -122,12 +178,213 @@ describe("sol-nft-staking", () => {
       owner
     );
 
-    //remove minting authority
-    nftMint.setAuthority(mintkeypair.publicKey, null, "MintTokens", owner, []);
-
     return [nftMint, nftTokenAccount];
   };
 
+  // Creates a rewarder for a new collection with the given custody mode, a
+  // stake account for a new owner and an NFT of the collection for the owner
+  const setupRewarder = async (collectionName: string, custodyMode: object) => {
+    const owner = anchor.web3.Keypair.generate();
+    const creator = anchor.web3.Keypair.generate();
+    const [rewarder, rewarderBump] =
+      await anchor.web3.PublicKey.findProgramAddress(
+        [
+          getCollectionSeed(collectionName),
+          solNftStakingProgram.programId.toBuffer(),
+          Buffer.from("rewarder"),
+        ],
+        solNftStakingProgram.programId
+      );
+    const [rewardAuthority, rewardAuthorityBump] =
+      await anchor.web3.PublicKey.findProgramAddress(
+        [
+          getCollectionSeed(collectionName),
+          solNftStakingProgram.programId.toBuffer(),
+          Buffer.from("rewarder"),
+          rewarder.toBuffer(),
+        ],
+        solNftStakingProgram.programId
+      );
+    const [stakeAccount, stakeAccountBump] =
+      await anchor.web3.PublicKey.findProgramAddress(
+        [
+          getCollectionSeed(collectionName),
+          solNftStakingProgram.programId.toBuffer(),
+          Buffer.from("stake_account"),
+          rewarder.toBuffer(),
+          owner.publicKey.toBuffer(),
+        ],
+        solNftStakingProgram.programId
+      );
+
+    await provider.connection.confirmTransaction(
+      await provider.connection.requestAirdrop(owner.publicKey, 1000000000),
+      "confirmed"
+    );
+
+    const rewardMint = await splToken.Token.createMint(
+      provider.connection,
+      owner,
+      rewardAuthority,
+      null,
+      3,
+      splToken.TOKEN_PROGRAM_ID
+    );
+    const rewardVault = await splToken.Token.getAssociatedTokenAddress(
+      splToken.ASSOCIATED_TOKEN_PROGRAM_ID,
+      splToken.TOKEN_PROGRAM_ID,
+      rewardMint.publicKey,
+      rewardAuthority,
+      true
+    );
+    const rewardTokenAccount = await rewardMint.createAssociatedTokenAccount(
+      owner.publicKey
+    );
+    const [nftMint, nftTokenAccount] = await mintNFT(
+      provider.connection,
+      owner,
+      creator
+    );
+
+    await solNftStakingProgram.rpc.initializeRewarder(
+      rewarderBump,
+      rewardAuthorityBump,
+      new anchor.BN(10),
+      Buffer.from(collectionName),
+      [{ address: creator.publicKey, verified: true, share: 100 }],
+      creator.publicKey,
+      true,
+      { mint: {} },
+      custodyMode,
+      { namePrefix: {} },
+      new anchor.BN(0),
+      {
+        accounts: {
+          rewarder,
+          authority: owner.publicKey,
+          rewardAuthority,
+          rewardMint: rewardMint.publicKey,
+          systemProgram,
+          rent: rentSysvar,
+          clock: clockSysvar,
+        },
+        signers: [owner],
+      }
+    );
+    await solNftStakingProgram.rpc.initializeStakeAccount(stakeAccountBump, {
+      accounts: {
+        owner: owner.publicKey,
+        stakeAccount,
+        rewarder,
+        systemProgram,
+        rent: rentSysvar,
+      },
+      signers: [owner],
+    });
+
+    const nftVault = await splToken.Token.getAssociatedTokenAddress(
+      splToken.ASSOCIATED_TOKEN_PROGRAM_ID,
+      splToken.TOKEN_PROGRAM_ID,
+      nftMint.publicKey,
+      stakeAccount,
+      true
+    );
+    const [stakeReceipt, stakeReceiptBump] =
+      await anchor.web3.PublicKey.findProgramAddress(
+        [
+          getCollectionSeed(collectionName),
+          solNftStakingProgram.programId.toBuffer(),
+          Buffer.from("stake_receipt"),
+          stakeAccount.toBuffer(),
+          nftMint.publicKey.toBuffer(),
+        ],
+        solNftStakingProgram.programId
+      );
+    const [blockedMint, _] = await anchor.web3.PublicKey.findProgramAddress(
+      [
+        getCollectionSeed(collectionName),
+        solNftStakingProgram.programId.toBuffer(),
+        Buffer.from("blocked_mint"),
+        rewarder.toBuffer(),
+        nftMint.publicKey.toBuffer(),
+      ],
+      solNftStakingProgram.programId
+    );
+
+    const stake = async () =>
+      await solNftStakingProgram.rpc.stakeNft(
+        stakeReceiptBump,
+        0,
+        10_000,
+        [],
+        [],
+        {
+          accounts: {
+            owner: owner.publicKey,
+            rewarder,
+            rewardAuthority,
+            rewardVault,
+            stakeAccount,
+            stakeReceipt,
+            rewardMint: rewardMint.publicKey,
+            rewardTokenAccount,
+            nftMint: nftMint.publicKey,
+            nftTokenAccount,
+            nftVault,
+            nftEdition: await getEditionAddress(nftMint.publicKey),
+            blockedMint,
+            tokenProgram: splToken.TOKEN_PROGRAM_ID,
+            associatedTokenProgram: splToken.ASSOCIATED_TOKEN_PROGRAM_ID,
+            tokenMetadataProgram,
+            systemProgram,
+            rent: rentSysvar,
+            clock: clockSysvar,
+          },
+          remainingAccounts: [
+            {
+              pubkey: await Metadata.getPDA(nftMint.publicKey),
+              isSigner: false,
+              isWritable: false,
+            },
+          ],
+          signers: [owner],
+        }
+      );
+    const unstake = async (tokenAccount: anchor.web3.PublicKey) =>
+      await solNftStakingProgram.rpc.unstakeNft({
+        accounts: {
+          owner: owner.publicKey,
+          rewarder,
+          rewardAuthority,
+          rewardVault,
+          stakeAccount,
+          stakeReceipt,
+          rewardMint: rewardMint.publicKey,
+          rewardTokenAccount,
+          nftMint: nftMint.publicKey,
+          nftTokenAccount: tokenAccount,
+          nftVault,
+          nftEdition: await getEditionAddress(nftMint.publicKey),
+          tokenProgram: splToken.TOKEN_PROGRAM_ID,
+          tokenMetadataProgram,
+          clock: clockSysvar,
+        },
+        signers: [owner],
+      });
+
+    return {
+      owner,
+      rewarder,
+      stakeAccount,
+      stakeReceipt,
+      nftMint,
+      nftTokenAccount,
+      nftVault,
+      stake,
+      unstake,
+    };
+  };
+
   describe("end to end test", async () => {
     const owner = anchor.web3.Keypair.generate();
     const creator = anchor.web3.Keypair.generate();
@@ -135,7 +392,7 @@ describe("sol-nft-staking", () => {
     const [rewarder, rewarderBump] =
       await anchor.web3.PublicKey.findProgramAddress(
         [
-          Buffer.from(collectionName),
+          getCollectionSeed(collectionName),
           solNftStakingProgram.programId.toBuffer(),
           Buffer.from("rewarder"),
         ],
@@ -144,7 +401,7 @@ describe("sol-nft-staking", () => {
     const [rewardAuthority, rewardAuthorityBump] =
       await anchor.web3.PublicKey.findProgramAddress(
         [
-          Buffer.from(collectionName),
+          getCollectionSeed(collectionName),
           solNftStakingProgram.programId.toBuffer(),
           Buffer.from("rewarder"),
           rewarder.toBuffer(),
@@ -154,7 +411,7 @@ describe("sol-nft-staking", () => {
     const [stakeAccount, stakeAccountBump] =
       await anchor.web3.PublicKey.findProgramAddress(
         [
-          Buffer.from(collectionName),
+          getCollectionSeed(collectionName),
           solNftStakingProgram.programId.toBuffer(),
           Buffer.from("stake_account"),
           rewarder.toBuffer(),
@@ -162,8 +419,121 @@ describe("sol-nft-staking", () => {
         ],
         solNftStakingProgram.programId
       );
+    const getStakeReceiptAddress = async (
+      mint: anchor.web3.PublicKey
+    ): Promise<[anchor.web3.PublicKey, number]> => {
+      return await anchor.web3.PublicKey.findProgramAddress(
+        [
+          getCollectionSeed(collectionName),
+          solNftStakingProgram.programId.toBuffer(),
+          Buffer.from("stake_receipt"),
+          stakeAccount.toBuffer(),
+          mint.toBuffer(),
+        ],
+        solNftStakingProgram.programId
+      );
+    };
+    const getBlockedMintAddress = async (
+      mint: anchor.web3.PublicKey
+    ): Promise<[anchor.web3.PublicKey, number]> => {
+      return await anchor.web3.PublicKey.findProgramAddress(
+        [
+          getCollectionSeed(collectionName),
+          solNftStakingProgram.programId.toBuffer(),
+          Buffer.from("blocked_mint"),
+          rewarder.toBuffer(),
+          mint.toBuffer(),
+        ],
+        solNftStakingProgram.programId
+      );
+    };
+    const stakeNft = async (
+      mint: anchor.web3.PublicKey,
+      tokenAccount: anchor.web3.PublicKey
+    ) => {
+      const [stakeReceipt, stakeReceiptBump] = await getStakeReceiptAddress(
+        mint
+      );
+      const [blockedMint, _] = await getBlockedMintAddress(mint);
+      return await solNftStakingProgram.rpc.stakeNft(
+        stakeReceiptBump,
+        0,
+        10_000,
+        [],
+        [],
+        {
+          accounts: {
+            owner: owner.publicKey,
+            rewarder,
+            rewardAuthority,
+            rewardVault,
+            stakeAccount,
+            stakeReceipt,
+            rewardMint: rewardMint.publicKey,
+            rewardTokenAccount,
+            nftMint: mint,
+            nftTokenAccount: tokenAccount,
+            nftVault: await splToken.Token.getAssociatedTokenAddress(
+              splToken.ASSOCIATED_TOKEN_PROGRAM_ID,
+              splToken.TOKEN_PROGRAM_ID,
+              mint,
+              stakeAccount,
+              true
+            ),
+            nftEdition: await getEditionAddress(mint),
+            blockedMint,
+            tokenProgram: splToken.TOKEN_PROGRAM_ID,
+            associatedTokenProgram: splToken.ASSOCIATED_TOKEN_PROGRAM_ID,
+            tokenMetadataProgram,
+            systemProgram,
+            rent: rentSysvar,
+            clock: clockSysvar,
+          },
+          remainingAccounts: [
+            {
+              pubkey: await Metadata.getPDA(mint),
+              isSigner: false,
+              isWritable: false,
+            },
+          ],
+          signers: [owner],
+        }
+      );
+    };
+    const forceUnstake = async (
+      mint: anchor.web3.PublicKey,
+      tokenAccount: anchor.web3.PublicKey
+    ) => {
+      const [stakeReceipt, _] = await getStakeReceiptAddress(mint);
+      const [blockedMint, __] = await getBlockedMintAddress(mint);
+      return await solNftStakingProgram.rpc.forceUnstake({
+        accounts: {
+          rewarder,
+          authority: owner.publicKey,
+          blockedMint,
+          owner: owner.publicKey,
+          stakeAccount,
+          stakeReceipt,
+          nftMint: mint,
+          nftTokenAccount: tokenAccount,
+          nftVault: await splToken.Token.getAssociatedTokenAddress(
+            splToken.ASSOCIATED_TOKEN_PROGRAM_ID,
+            splToken.TOKEN_PROGRAM_ID,
+            mint,
+            stakeAccount,
+            true
+          ),
+          nftEdition: await getEditionAddress(mint),
+          tokenProgram: splToken.TOKEN_PROGRAM_ID,
+          tokenMetadataProgram,
+          clock: clockSysvar,
+        },
+        signers: [owner],
+      });
+    };
     const rewardRate = 10;
     let rewardMint = null;
+    let rewardVault = null;
     let rewardTokenAccount = null;
     let nftMint = null;
     let nftTokenAccount = null;
@@ -186,6 +556,14 @@ describe("sol-nft-staking", () => {
         splToken.TOKEN_PROGRAM_ID
       );
 
+      rewardVault = await splToken.Token.getAssociatedTokenAddress(
+        splToken.ASSOCIATED_TOKEN_PROGRAM_ID,
+        splToken.TOKEN_PROGRAM_ID,
+        rewardMint.publicKey,
+        rewardAuthority,
+        true
+      );
+
       console.log("creating reward token account");
       rewardTokenAccount = await rewardMint.createAssociatedTokenAccount(
         owner.publicKey
@@ -212,6 +590,10 @@ describe("sol-nft-staking", () => {
         creators,
         creator.publicKey,
         true,
+        { mint: {} },
+        { transferAuthority: {} },
+        { namePrefix: {} },
+        new anchor.BN(0),
         {
           accounts: {
             rewarder: rewarder,
@@ -220,10 +602,15 @@ describe("sol-nft-staking", () => {
             rewardMint: rewardMint.publicKey,
             systemProgram,
             rent: rentSysvar,
+            clock: clockSysvar,
           },
           signers: [owner],
         }
       );
+
+      const rewarderData =
+        await solNftStakingProgram.account.nftStakeRewarder.fetch(rewarder);
+      expect(rewarderData.version).to.equal(1);
     });
 
     it("initialized a stake account", async () => {
@@ -237,33 +624,81 @@ describe("sol-nft-staking", () => {
         },
         signers: [owner],
       });
+
+      const stakeAccountData =
+        await solNftStakingProgram.account.nftStakeAccount.fetch(stakeAccount);
+      expect(stakeAccountData.version).to.equal(1);
     });
 
     it("stakes an NFT", async () => {
       const nftMetadata = await Metadata.getPDA(nftMint.publicKey);
-      await solNftStakingProgram.rpc.stakeNft({
-        accounts: {
-          owner: owner.publicKey,
-          rewarder,
-          rewardAuthority,
-          stakeAccount,
-          rewardMint: rewardMint.publicKey,
-          rewardTokenAccount,
-          nftMint: nftMint.publicKey,
-          nftTokenAccount,
-          tokenProgram: splToken.TOKEN_PROGRAM_ID,
-          systemProgram,
-          rent: rentSysvar,
-          clock: clockSysvar,
-        },
-        remainingAccounts: [
-          { pubkey: nftMetadata, isSigner: false, isWritable: false },
-        ],
-        signers: [owner],
-      });
+      const nftVault = await splToken.Token.getAssociatedTokenAddress(
+        splToken.ASSOCIATED_TOKEN_PROGRAM_ID,
+        splToken.TOKEN_PROGRAM_ID,
+        nftMint.publicKey,
+        stakeAccount,
+        true
+      );
+      const [stakeReceipt, stakeReceiptBump] = await getStakeReceiptAddress(
+        nftMint.publicKey
+      );
+      //the mint hasn't been blocked so the blocked mint PDA doesn't exist
+      const [blockedMint, _] = await getBlockedMintAddress(nftMint.publicKey);
+      //stake in the default tier, which has no lockup and a 1x multiplier
+      const lockupTier = 0;
+      //the rewarder has no weights root so every nft earns at 1x without a proof
+      const weight = 10_000;
+      const weightProof = [];
+      //nor an allowlist root so the nft doesn't need an allowlist proof
+      const allowlistProof = [];
+      await solNftStakingProgram.rpc.stakeNft(
+        stakeReceiptBump,
+        lockupTier,
+        weight,
+        weightProof,
+        allowlistProof,
+        {
+          accounts: {
+            owner: owner.publicKey,
+            rewarder,
+            rewardAuthority,
+            rewardVault,
+            stakeAccount,
+            stakeReceipt,
+            rewardMint: rewardMint.publicKey,
+            rewardTokenAccount,
+            nftMint: nftMint.publicKey,
+            nftTokenAccount,
+            nftVault,
+            nftEdition: await getEditionAddress(nftMint.publicKey),
+            blockedMint,
+            tokenProgram: splToken.TOKEN_PROGRAM_ID,
+            associatedTokenProgram: splToken.ASSOCIATED_TOKEN_PROGRAM_ID,
+            tokenMetadataProgram,
+            systemProgram,
+            rent: rentSysvar,
+            clock: clockSysvar,
+          },
+          remainingAccounts: [
+            { pubkey: nftMetadata, isSigner: false, isWritable: false },
+          ],
+          signers: [owner],
+        }
+      );
 
       let nftAccount = await nftMint.getAccountInfo(nftTokenAccount);
       expect(nftAccount.owner.toBase58()).to.equal(stakeAccount.toBase58());
+
+      const receipt = await solNftStakingProgram.account.stakeReceipt.fetch(
+        stakeReceipt
+      );
+      expect(receipt.stakeAccount.toBase58()).to.equal(stakeAccount.toBase58());
+      expect(receipt.mint.toBase58()).to.equal(nftMint.publicKey.toBase58());
+      expect(receipt.multiplier).to.equal(10_000);
+      expect(receipt.weight.toNumber()).to.equal(10_000);
+      expect(receipt.lockupEnd.toNumber()).to.equal(
+        receipt.stakedAt.toNumber()
+      );
     });
 
     it("claims pending rewards", async () => {
@@ -279,6 +714,7 @@ describe("sol-nft-staking", () => {
           owner: owner.publicKey,
           rewarder,
           rewardAuthority,
+          rewardVault,
           stakeAccount,
           rewardMint: rewardMint.publicKey,
           rewardAccount: rewardTokenAccount,
@@ -299,18 +735,33 @@ describe("sol-nft-staking", () => {
     it("unstakes an NFT", async () => {
       //sleep one more second to check that we claim pending rewards on unstake
       await sleep(provider.connection, 1);
+      const nftVault = await splToken.Token.getAssociatedTokenAddress(
+        splToken.ASSOCIATED_TOKEN_PROGRAM_ID,
+        splToken.TOKEN_PROGRAM_ID,
+        nftMint.publicKey,
+        stakeAccount,
+        true
+      );
+      const [stakeReceipt, _] = await getStakeReceiptAddress(
+        nftMint.publicKey
+      );
 
       await solNftStakingProgram.rpc.unstakeNft({
         accounts: {
           owner: owner.publicKey,
           rewarder,
           rewardAuthority,
+          rewardVault,
           stakeAccount,
+          stakeReceipt,
           rewardMint: rewardMint.publicKey,
           rewardTokenAccount,
           nftMint: nftMint.publicKey,
           nftTokenAccount,
+          nftVault,
+          nftEdition: await getEditionAddress(nftMint.publicKey),
           tokenProgram: splToken.TOKEN_PROGRAM_ID,
+          tokenMetadataProgram,
           clock: clockSysvar,
         },
         signers: [owner],
@@ -322,6 +773,358 @@ describe("sol-nft-staking", () => {
       expect(rewardTokenAccountData.amount.toNumber()).to.equal(3 * rewardRate);
       let nftAccount = await nftMint.getAccountInfo(nftTokenAccount);
       expect(nftAccount.owner.toBase58()).to.equal(owner.publicKey.toBase58());
+
+      const receiptInfo = await provider.connection.getAccountInfo(
+        stakeReceipt
+      );
+      expect(receiptInfo).to.be.null;
+    });
+
+    it("rejects force unstaking a mint that isn't blocked", async () => {
+      await stakeNft(nftMint.publicKey, nftTokenAccount);
+
+      //the blocked mint PDA doesn't exist
+      await expectProgramError(
+        forceUnstake(nftMint.publicKey, nftTokenAccount),
+        "MintNotBlocked"
+      );
+
+      const [stakeReceipt, _] = await getStakeReceiptAddress(
+        nftMint.publicKey
+      );
+      const receiptInfo = await provider.connection.getAccountInfo(
+        stakeReceipt
+      );
+      expect(receiptInfo).to.not.be.null;
+    });
+
+    it("blocks a staked mint", async () => {
+      const [stakeReceipt, _] = await getStakeReceiptAddress(
+        nftMint.publicKey
+      );
+      const [blockedMint, blockedMintBump] = await getBlockedMintAddress(
+        nftMint.publicKey
+      );
+
+      await solNftStakingProgram.rpc.blockMint(blockedMintBump, {
+        accounts: {
+          rewarder,
+          authority: owner.publicKey,
+          nftMint: nftMint.publicKey,
+          blockedMint,
+          systemProgram,
+          rent: rentSysvar,
+          clock: clockSysvar,
+        },
+        remainingAccounts: [
+          { pubkey: stakeAccount, isSigner: false, isWritable: true },
+          { pubkey: stakeReceipt, isSigner: false, isWritable: true },
+        ],
+        signers: [owner],
+      });
+
+      //the blocked nft stops earning
+      const receipt = await solNftStakingProgram.account.stakeReceipt.fetch(
+        stakeReceipt
+      );
+      expect(receipt.blocked).to.be.true;
+      const stakeAccountData =
+        await solNftStakingProgram.account.nftStakeAccount.fetch(stakeAccount);
+      expect(stakeAccountData.numBlocked).to.equal(1);
+      expect(stakeAccountData.totalWeight.toNumber()).to.equal(0);
+
+      //and the stake account can't claim while it holds a blocked nft
+      await expectProgramError(
+        solNftStakingProgram.rpc.claim({
+          accounts: {
+            owner: owner.publicKey,
+            rewarder,
+            rewardAuthority,
+            rewardVault,
+            stakeAccount,
+            rewardMint: rewardMint.publicKey,
+            rewardAccount: rewardTokenAccount,
+            tokenProgram: splToken.TOKEN_PROGRAM_ID,
+            clock: clockSysvar,
+          },
+          signers: [owner],
+        }),
+        "MintBlocked"
+      );
+    });
+
+    it("holds back rewards when another NFT is unstaked while one is blocked", async () => {
+      const [otherMint, otherTokenAccount] = await mintNFT(
+        provider.connection,
+        owner,
+        creator
+      );
+      const rewardsBefore = await rewardMint.getAccountInfo(rewardTokenAccount);
+
+      await stakeNft(otherMint.publicKey, otherTokenAccount);
+      await sleep(provider.connection, 1);
+      const [stakeReceipt, _] = await getStakeReceiptAddress(
+        otherMint.publicKey
+      );
+      await solNftStakingProgram.rpc.unstakeNft({
+        accounts: {
+          owner: owner.publicKey,
+          rewarder,
+          rewardAuthority,
+          rewardVault,
+          stakeAccount,
+          stakeReceipt,
+          rewardMint: rewardMint.publicKey,
+          rewardTokenAccount,
+          nftMint: otherMint.publicKey,
+          nftTokenAccount: otherTokenAccount,
+          nftVault: await splToken.Token.getAssociatedTokenAddress(
+            splToken.ASSOCIATED_TOKEN_PROGRAM_ID,
+            splToken.TOKEN_PROGRAM_ID,
+            otherMint.publicKey,
+            stakeAccount,
+            true
+          ),
+          nftEdition: await getEditionAddress(otherMint.publicKey),
+          tokenProgram: splToken.TOKEN_PROGRAM_ID,
+          tokenMetadataProgram,
+          clock: clockSysvar,
+        },
+        signers: [owner],
+      });
+
+      //the other nft is returned but its rewards stay pending
+      const nftAccount = await otherMint.getAccountInfo(otherTokenAccount);
+      expect(nftAccount.owner.toBase58()).to.equal(owner.publicKey.toBase58());
+      const rewardsAfter = await rewardMint.getAccountInfo(rewardTokenAccount);
+      expect(rewardsAfter.amount.toNumber()).to.equal(
+        rewardsBefore.amount.toNumber()
+      );
+      const stakeAccountData =
+        await solNftStakingProgram.account.nftStakeAccount.fetch(stakeAccount);
+      expect(stakeAccountData.pendingReward.toNumber()).to.be.greaterThan(0);
+      expect(stakeAccountData.numStaked).to.equal(1);
+    });
+
+    it("force unstakes a blocked mint", async () => {
+      const [stakeReceipt, _] = await getStakeReceiptAddress(
+        nftMint.publicKey
+      );
+      await forceUnstake(nftMint.publicKey, nftTokenAccount);
+
+      const nftAccount = await nftMint.getAccountInfo(nftTokenAccount);
+      expect(nftAccount.owner.toBase58()).to.equal(owner.publicKey.toBase58());
+      const receiptInfo = await provider.connection.getAccountInfo(
+        stakeReceipt
+      );
+      expect(receiptInfo).to.be.null;
+      const stakeAccountData =
+        await solNftStakingProgram.account.nftStakeAccount.fetch(stakeAccount);
+      expect(stakeAccountData.numStaked).to.equal(0);
+      expect(stakeAccountData.numBlocked).to.equal(0);
+    });
+
+    it("rejects staking a blocked mint", async () => {
+      await expectProgramError(
+        stakeNft(nftMint.publicKey, nftTokenAccount),
+        "MintBlocked"
+      );
+    });
+  });
+
+  describe("escrow custody", async () => {
+    let staking = null;
+
+    before(async () => {
+      staking = await setupRewarder("escrow", { escrow: {} });
+    });
+
+    it("holds a staked NFT in the stake account's vault", async () => {
+      const { nftMint, nftTokenAccount, nftVault, stakeAccount } = staking;
+      await staking.stake();
+
+      const ownerAccount = await nftMint.getAccountInfo(nftTokenAccount);
+      expect(ownerAccount.amount.toNumber()).to.equal(0);
+      const vaultAccount = await nftMint.getAccountInfo(nftVault);
+      expect(vaultAccount.owner.toBase58()).to.equal(stakeAccount.toBase58());
+      expect(vaultAccount.amount.toNumber()).to.equal(1);
+    });
+
+    it("unstakes to any token account of the owner and closes the vault", async () => {
+      const { owner, nftMint, nftVault, stakeReceipt } = staking;
+      //a token account that isn't the owner's associated token account
+      const tokenAccount = await nftMint.createAccount(owner.publicKey);
+
+      const vaultRent = await provider.connection.getBalance(nftVault);
+      const receiptRent = await provider.connection.getBalance(stakeReceipt);
+      const balanceBefore = await provider.connection.getBalance(
+        owner.publicKey
+      );
+
+      await staking.unstake(tokenAccount);
+
+      const nftAccount = await nftMint.getAccountInfo(tokenAccount);
+      expect(nftAccount.amount.toNumber()).to.equal(1);
+      const vaultInfo = await provider.connection.getAccountInfo(nftVault);
+      expect(vaultInfo).to.be.null;
+
+      //the provider pays the fees, so the owner gets back exactly the rent of the
+      //vault and the receipt
+      const balanceAfter = await provider.connection.getBalance(
+        owner.publicKey
+      );
+      expect(balanceAfter - balanceBefore).to.equal(vaultRent + receiptRent);
+    });
+  });
+
+  describe("freeze custody", async () => {
+    let staking = null;
+
+    before(async () => {
+      staking = await setupRewarder("freeze", { freeze: {} });
+    });
+
+    it("freezes a staked NFT in the owner's token account", async () => {
+      const { owner, nftMint, nftTokenAccount, stakeAccount } = staking;
+      await staking.stake();
+
+      const nftAccount = await nftMint.getAccountInfo(nftTokenAccount);
+      expect(nftAccount.owner.toBase58()).to.equal(owner.publicKey.toBase58());
+      expect(nftAccount.amount.toNumber()).to.equal(1);
+      expect(nftAccount.isFrozen).to.be.true;
+      expect(nftAccount.delegate.toBase58()).to.equal(stakeAccount.toBase58());
+      expect(nftAccount.delegatedAmount.toNumber()).to.equal(1);
+    });
+
+    it("thaws the NFT and revokes the delegate on unstake", async () => {
+      const { nftMint, nftTokenAccount } = staking;
+      await staking.unstake(nftTokenAccount);
+
+      const nftAccount = await nftMint.getAccountInfo(nftTokenAccount);
+      expect(nftAccount.amount.toNumber()).to.equal(1);
+      expect(nftAccount.isFrozen).to.be.false;
+      expect(nftAccount.delegate).to.be.null;
+    });
+  });
+
+  describe("growing a rewarder", async () => {
+    let staking = null;
+
+    before(async () => {
+      staking = await setupRewarder("creators", { transferAuthority: {} });
+    });
+
+    it("reallocates the rewarder for more creators", async () => {
+      const { owner, rewarder } = staking;
+      const lenBefore = (await provider.connection.getAccountInfo(rewarder))
+        .data.length;
+
+      //grow from the 1 creator the rewarder was created with to the maximum
+      const creators = [...Array(5)].map(() => ({
+        address: anchor.web3.Keypair.generate().publicKey,
+        verified: true,
+        share: 20,
+      }));
+      await solNftStakingProgram.rpc.updateCreators(creators, {
+        accounts: {
+          rewarder,
+          authority: owner.publicKey,
+          systemProgram,
+          rent: rentSysvar,
+        },
+        signers: [owner],
+      });
+
+      //each creator takes up 34 bytes
+      const rewarderInfo = await provider.connection.getAccountInfo(rewarder);
+      expect(rewarderInfo.data.length).to.equal(lenBefore + 4 * 34);
+      const rewarderData =
+        await solNftStakingProgram.account.nftStakeRewarder.fetch(rewarder);
+      expect(rewarderData.creators.length).to.equal(5);
+      rewarderData.creators.forEach((creator, i) => {
+        expect(creator.address.toBase58()).to.equal(
+          creators[i].address.toBase58()
+        );
+        expect(creator.share).to.equal(20);
+      });
+      expect(rewarderData.collection).to.equal("creators");
+      expect(rewarderData.version).to.equal(1);
+    });
+  });
+
+  describe("transferring ownership", async () => {
+    const newAuthority = anchor.web3.Keypair.generate();
+    let staking = null;
+
+    before(async () => {
+      staking = await setupRewarder("authority", { transferAuthority: {} });
+    });
+
+    it("proposes a new authority", async () => {
+      const { owner, rewarder } = staking;
+      await solNftStakingProgram.rpc.proposeAuthority(newAuthority.publicKey, {
+        accounts: {
+          rewarder,
+          authority: owner.publicKey,
+        },
+        signers: [owner],
+      });
+
+      const rewarderData =
+        await solNftStakingProgram.account.nftStakeRewarder.fetch(rewarder);
+      expect(rewarderData.pendingAuthority.toBase58()).to.equal(
+        newAuthority.publicKey.toBase58()
+      );
+    });
+
+    it("rejects accepting from anyone but the pending authority", async () => {
+      const { rewarder } = staking;
+      const stranger = anchor.web3.Keypair.generate();
+      await expectProgramError(
+        solNftStakingProgram.rpc.acceptAuthority({
+          accounts: {
+            rewarder,
+            pendingAuthority: stranger.publicKey,
+          },
+          signers: [stranger],
+        }),
+        "InvalidPendingAuthority"
+      );
+    });
+
+    it("hands over the rewarder when the pending authority accepts", async () => {
+      const { rewarder } = staking;
+      await solNftStakingProgram.rpc.acceptAuthority({
+        accounts: {
+          rewarder,
+          pendingAuthority: newAuthority.publicKey,
+        },
+        signers: [newAuthority],
+      });
+
+      const rewarderData =
+        await solNftStakingProgram.account.nftStakeRewarder.fetch(rewarder);
+      expect(rewarderData.authority.toBase58()).to.equal(
+        newAuthority.publicKey.toBase58()
+      );
+      expect(
+        rewarderData.pendingAuthority.equals(anchor.web3.PublicKey.default)
+      ).to.be.true;
+    });
+
+    it("rejects the old authority", async () => {
+      const { owner, rewarder } = staking;
+      await expectProgramError(
+        solNftStakingProgram.rpc.updateRewardRate(new anchor.BN(20), {
+          accounts: {
+            rewarder,
+            authority: owner.publicKey,
+            clock: clockSysvar,
+          },
+          signers: [owner],
+        }),
+        "InvalidRewarderAuthority"
+      );
     });
   });
 });
@@ -3,16 +3,24 @@ import { Program, web3 } from "@project-serum/anchor";
 import { SolNftStaking } from "../target/types/sol_nft_staking";
 import { Command, program as cliProgram } from "commander";
 import * as fs from "fs";
+import { createHash } from "crypto";
 import * as splToken from "@solana/spl-token";
 
 const SOL_NFT_STAKING_PROGRAM_ID = new web3.PublicKey(
   "3zPPaZhN3tAkSJhjcEcyT7kAM6b2stQmJf65Fw9sMZa3"
 );
 
+const TOKEN_METADATA_PROGRAM_ID = new web3.PublicKey(
+  "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s"
+);
+
 const systemProgram = anchor.web3.SystemProgram.programId;
 const rentSysvar = anchor.web3.SYSVAR_RENT_PUBKEY;
 const clockSysvar = anchor.web3.SYSVAR_CLOCK_PUBKEY;
 
+// reward multipliers are expressed in basis points
+const BASE_MULTIPLIER = 10_000;
+
 function cliCommand(name: string): Command {
   return cliProgram
     .command(name)
@@ -25,6 +33,11 @@ function cliCommand(name: string): Command {
       "-k, --keypair <path>",
       `Solana wallet location`,
       "--keypair not provided"
+    )
+    .option(
+      "--pool <number>",
+      "The pool of the collection's rewarder, 0 for the collection's first rewarder",
+      "0"
     );
 }
 
@@ -47,18 +60,42 @@ interface Creator {
 
 function loadCreators(creators): Creator[] {
   let parsedCreators = [];
+  if (!creators) {
+    return parsedCreators;
+  }
 
+  const loaded = JSON.parse(fs.readFileSync(creators).toString());
+  for (const creator of loaded) {
+    parsedCreators.push({
+      address: new web3.PublicKey(creator.address),
+      verified: !!creator.verified,
+      share: creator.share,
+    });
+  }
   return parsedCreators;
 }
 
+// Pool 0 has no seed so it keeps the address rewarders had before pools
+// names longer than a seed are hashed, see get_collection_seed in the program
+function getCollectionSeed(collectionName: string): Buffer {
+  const name = Buffer.from(collectionName);
+  return name.length > 32 ? createHash("sha256").update(name).digest() : name;
+}
+
+function getPoolSeed(pool: anchor.BN): Buffer {
+  return pool.isZero() ? Buffer.alloc(0) : pool.toArrayLike(Buffer, "le", 8);
+}
+
 async function getRewarderAddress(
-  collectionName: string
+  collectionName: string,
+  pool: string
 ): Promise<[web3.PublicKey, number]> {
   return await anchor.web3.PublicKey.findProgramAddress(
     [
-      Buffer.from(collectionName),
+      getCollectionSeed(collectionName),
       SOL_NFT_STAKING_PROGRAM_ID.toBuffer(),
       Buffer.from("rewarder"),
+      getPoolSeed(new anchor.BN(pool)),
     ],
     SOL_NFT_STAKING_PROGRAM_ID
   );
@@ -70,17 +107,171 @@ async function getRewarderAuthority(
 ): Promise<[web3.PublicKey, number]> {
   return await anchor.web3.PublicKey.findProgramAddress(
     [
-      Buffer.from(collectionName),
+      getCollectionSeed(collectionName),
+      SOL_NFT_STAKING_PROGRAM_ID.toBuffer(),
+      Buffer.from("rewarder"),
+      rewarderAddress.toBuffer(),
+    ],
+    SOL_NFT_STAKING_PROGRAM_ID
+  );
+}
+
+async function getStreamAuthority(
+  collectionName: string,
+  rewarderAddress: web3.PublicKey,
+  rewardMint: web3.PublicKey
+): Promise<[web3.PublicKey, number]> {
+  return await anchor.web3.PublicKey.findProgramAddress(
+    [
+      getCollectionSeed(collectionName),
       SOL_NFT_STAKING_PROGRAM_ID.toBuffer(),
       Buffer.from("rewarder"),
       rewarderAddress.toBuffer(),
+      rewardMint.toBuffer(),
+    ],
+    SOL_NFT_STAKING_PROGRAM_ID
+  );
+}
+
+async function getStakeAccountAddress(
+  collectionName: string,
+  rewarderAddress: web3.PublicKey,
+  owner: web3.PublicKey
+): Promise<[web3.PublicKey, number]> {
+  return await anchor.web3.PublicKey.findProgramAddress(
+    [
+      getCollectionSeed(collectionName),
+      SOL_NFT_STAKING_PROGRAM_ID.toBuffer(),
+      Buffer.from("stake_account"),
+      rewarderAddress.toBuffer(),
+      owner.toBuffer(),
+    ],
+    SOL_NFT_STAKING_PROGRAM_ID
+  );
+}
+
+async function getStakeReceiptAddress(
+  collectionName: string,
+  stakeAccount: web3.PublicKey,
+  mint: web3.PublicKey
+): Promise<[web3.PublicKey, number]> {
+  return await anchor.web3.PublicKey.findProgramAddress(
+    [
+      getCollectionSeed(collectionName),
+      SOL_NFT_STAKING_PROGRAM_ID.toBuffer(),
+      Buffer.from("stake_receipt"),
+      stakeAccount.toBuffer(),
+      mint.toBuffer(),
+    ],
+    SOL_NFT_STAKING_PROGRAM_ID
+  );
+}
+
+async function getBlockedMintAddress(
+  collectionName: string,
+  rewarderAddress: web3.PublicKey,
+  mint: web3.PublicKey
+): Promise<[web3.PublicKey, number]> {
+  return await anchor.web3.PublicKey.findProgramAddress(
+    [
+      getCollectionSeed(collectionName),
+      SOL_NFT_STAKING_PROGRAM_ID.toBuffer(),
+      Buffer.from("blocked_mint"),
+      rewarderAddress.toBuffer(),
+      mint.toBuffer(),
     ],
     SOL_NFT_STAKING_PROGRAM_ID
   );
 }
 
-function printRewarder(address: web3.PublicKey, rewarder: any) {
-  const toLog = {
+async function findStakeReceipt(
+  solNftStakingProgram: Program<SolNftStaking>,
+  rewarder: web3.PublicKey,
+  nftMint: web3.PublicKey
+) {
+  // the mint is the second field of a receipt, after the discriminator and the stake account
+  const receipts = await solNftStakingProgram.account.stakeReceipt.all([
+    { memcmp: { offset: 8 + 32, bytes: nftMint.toBase58() } },
+  ]);
+  for (const receipt of receipts) {
+    const stakeAccount =
+      await solNftStakingProgram.account.nftStakeAccount.fetch(
+        receipt.account.stakeAccount
+      );
+    if (stakeAccount.rewarder.equals(rewarder)) {
+      return receipt;
+    }
+  }
+  return null;
+}
+
+async function getEditionAddress(
+  mint: web3.PublicKey
+): Promise<web3.PublicKey> {
+  const [edition, _] = await anchor.web3.PublicKey.findProgramAddress(
+    [
+      Buffer.from("metadata"),
+      TOKEN_METADATA_PROGRAM_ID.toBuffer(),
+      mint.toBuffer(),
+      Buffer.from("edition"),
+    ],
+    TOKEN_METADATA_PROGRAM_ID
+  );
+  return edition;
+}
+
+async function getRewardVault(
+  rewardMint: web3.PublicKey,
+  rewardAuthority: web3.PublicKey
+): Promise<web3.PublicKey> {
+  return await splToken.Token.getAssociatedTokenAddress(
+    splToken.ASSOCIATED_TOKEN_PROGRAM_ID,
+    splToken.TOKEN_PROGRAM_ID,
+    rewardMint,
+    rewardAuthority,
+    true
+  );
+}
+
+// Returns the number of seconds the vault can keep paying rewards, or null if nothing is being earned
+function calculateRunway(
+  vaultBalance: anchor.BN,
+  rewardRate: anchor.BN,
+  totalWeight: anchor.BN
+): anchor.BN | null {
+  const emissionRate = rewardRate.mul(totalWeight);
+  if (emissionRate.isZero()) {
+    return null;
+  }
+  return vaultBalance.muln(BASE_MULTIPLIER).div(emissionRate);
+}
+
+// Parses lockup tiers given as comma separated duration:multiplier pairs, e.g. "0:10000,2592000:15000"
+function parseLockupTiers(tiers: string): any[] {
+  return tiers.split(",").map((tier) => {
+    const [duration, multiplier] = tier.split(":");
+    return {
+      duration: new anchor.BN(duration),
+      multiplier: parseInt(multiplier),
+    };
+  });
+}
+
+// Parses a hex encoded 32 byte Merkle root
+function parseMerkleRoot(root: string): number[] {
+  const parsed = Buffer.from(root.replace(/^0x/, ""), "hex");
+  if (parsed.length !== 32) {
+    throw new Error("Merkle roots must be 32 bytes");
+  }
+  return [...parsed];
+}
+
+function printRewarder(
+  address: web3.PublicKey,
+  rewarder: any,
+  vaultBalance: anchor.BN | null = null
+) {
+  const toLog: any = {
     authority: rewarder.authority.toBase58(),
     rewardMint: rewarder.rewardMint.toBase58(),
     rewardAuthorityBump: rewarder.rewardAuthorityBump,
@@ -90,7 +281,60 @@ function printRewarder(address: web3.PublicKey, rewarder: any) {
     collection: rewarder.collection,
     enforceMetadata: rewarder.enforceMetadata,
     totalStaked: rewarder.totalStaked,
+    totalWeight: rewarder.totalWeight.toString(),
+    lockupTiers: rewarder.lockupTiers.map((tier) => ({
+      duration: tier.duration.toNumber(),
+      multiplier: tier.multiplier,
+    })),
+    unbondingPeriod: rewarder.unbondingPeriod.toNumber(),
+    earlyUnstakePenaltyBps: rewarder.earlyUnstakePenaltyBps,
+    weightsRoot: Buffer.from(rewarder.weightsRoot).toString("hex"),
+    allowlistRoot: Buffer.from(rewarder.allowlistRoot).toString("hex"),
+    pendingAuthority: rewarder.pendingAuthority.equals(web3.PublicKey.default)
+      ? null
+      : rewarder.pendingAuthority.toBase58(),
+    paused: rewarder.paused,
+    schedule: {
+      startTime: rewarder.schedule.startTime.toNumber(),
+      endTime: rewarder.schedule.endTime.toNumber(),
+      halvingInterval: rewarder.schedule.halvingInterval.toNumber(),
+    },
+    emissionCap: rewarder.emissionCap.toString(),
+    totalEmitted: rewarder.totalEmitted.toString(),
+    rewardStreams: rewarder.rewardStreams.map((stream) => ({
+      rewardMint: stream.rewardMint.toBase58(),
+      rewardAuthorityBump: stream.rewardAuthorityBump,
+      rewardRate: stream.rewardRate.toNumber(),
+      rewardMode: Object.keys(stream.rewardMode)[0],
+      accruedRewardPerNft: stream.accruedRewardPerNft.toString(),
+    })),
+    rewardMode: Object.keys(rewarder.rewardMode)[0],
+    custodyMode: Object.keys(rewarder.custodyMode)[0],
+    poolId: rewarder.poolId.toString(),
+    version: rewarder.version,
+    collectionStrategy: rewarder.collectionStrategy.verifiedCollection
+      ? {
+          verifiedCollection:
+            rewarder.collectionStrategy.verifiedCollection.mint.toBase58(),
+        }
+      : "namePrefix",
+    metadataPolicy: {
+      ...rewarder.metadataPolicy,
+      creatorMatch: Object.keys(rewarder.metadataPolicy.creatorMatch)[0],
+    },
+    additionalUpdateAuthorities: rewarder.additionalUpdateAuthorities.map(
+      (authority) => authority.toBase58()
+    ),
   };
+  if (vaultBalance !== null) {
+    const runway = calculateRunway(
+      vaultBalance,
+      rewarder.rewardRate,
+      rewarder.totalWeight
+    );
+    toLog.vaultBalance = vaultBalance.toString();
+    toLog.runwaySeconds = runway === null ? "unlimited" : runway.toString();
+  }
   console.log(
     `Rewarder ${address.toBase58()}\n${JSON.stringify(toLog, null, 2)}`
   );
@@ -109,7 +353,7 @@ rewarderCommand
     null
   )
   .action(async (directory: string, cmd: Command) => {
-    const { env } = cmd.parent.opts();
+    const { env, pool } = cmd.parent.opts();
     const { name, address } = cmd.opts();
     const connection = new web3.Connection(web3.clusterApiUrl(env));
 
@@ -122,7 +366,7 @@ rewarderCommand
     if (address && address.length !== 0) {
       key = new web3.PublicKey(address);
     } else {
-      const [rewarderAddress, _] = await getRewarderAddress(name);
+      const [rewarderAddress, _] = await getRewarderAddress(name, pool);
       key = rewarderAddress;
     }
 
@@ -137,7 +381,21 @@ rewarderCommand
     const rewarder = await solNftStakingProgram.account.nftStakeRewarder.fetch(
       key
     );
-    printRewarder(key, rewarder);
+
+    let vaultBalance = null;
+    if (rewarder.rewardMode.vault) {
+      const [rewardAuthority, _] = await getRewarderAuthority(
+        rewarder.collection,
+        key
+      );
+      const rewardVault = await getRewardVault(
+        rewarder.rewardMint,
+        rewardAuthority
+      );
+      const vaultInfo = await connection.getTokenAccountBalance(rewardVault);
+      vaultBalance = new anchor.BN(vaultInfo.value.amount);
+    }
+    printRewarder(key, rewarder, vaultBalance);
   });
 
 rewarderCommand
@@ -158,9 +416,41 @@ rewarderCommand
     "-c, --creators <path>",
     "the path to a json array of nft creator objects"
   )
+  .option(
+    "-m, --mode <string>",
+    "How rewards are paid out. 'mint' to mint new tokens or 'vault' to transfer from a pre-funded vault",
+    "mint"
+  )
+  .option(
+    "-s, --custody <string>",
+    "How staked NFTs are held. 'transferAuthority' to take ownership of the owner's token account, 'escrow' to transfer NFTs into a vault or 'freeze' to freeze NFTs in the owner's wallet",
+    "transferAuthority"
+  )
+  .option(
+    "-v, --verifiedCollection <string>",
+    "The mint of a Metaplex collection that staked NFTs must be verified members of, instead of matching the name prefix"
+  )
   .action(async (directory: string, cmd: Command) => {
-    const { env, keypair } = cmd.parent.opts();
-    const { decimals, name, rewardRate, creators } = cmd.opts();
+    const { env, keypair, pool } = cmd.parent.opts();
+    const {
+      decimals,
+      name,
+      rewardRate,
+      creators,
+      mode,
+      custody,
+      verifiedCollection,
+    } = cmd.opts();
+    if (mode !== "mint" && mode !== "vault") {
+      console.log("mode must be either 'mint' or 'vault'");
+      return;
+    }
+    if (!["transferAuthority", "escrow", "freeze"].includes(custody)) {
+      console.log(
+        "custody must be one of 'transferAuthority', 'escrow' or 'freeze'"
+      );
+      return;
+    }
     const collectionName = name;
     const connection = new web3.Connection(web3.clusterApiUrl(env));
     const walletKeyPair = loadWalletKey(keypair);
@@ -173,6 +463,13 @@ rewarderCommand
     );
 
     const parsedCreators = loadCreators(creators);
+    const collectionStrategy = verifiedCollection
+      ? {
+          verifiedCollection: {
+            mint: new web3.PublicKey(verifiedCollection),
+          },
+        }
+      : { namePrefix: {} };
 
     const solNftStakingProgram = (await Program.at(
       SOL_NFT_STAKING_PROGRAM_ID
@@ -180,7 +477,7 @@ rewarderCommand
     console.log(`Creating rewarder for '${name}'`);
 
     console.log(`Finding PDAs for rewarder and mint authority`);
-    const [rewarder, rewarderBump] = await getRewarderAddress(collectionName);
+    const [rewarder, rewarderBump] = await getRewarderAddress(collectionName, pool);
     const [rewardAuthority, rewardAuthorityBump] = await getRewarderAuthority(
       collectionName,
       rewarder
@@ -189,13 +486,34 @@ rewarderCommand
     const rewardMint = await splToken.Token.createMint(
       connection,
       walletKeyPair, //payer
-      rewardAuthority, //mint authority
+      mode === "mint" ? rewardAuthority : wallet.publicKey, //mint authority
       null, //freeze authority
       decimals, //deicmals
       splToken.TOKEN_PROGRAM_ID
     );
     console.log(`Reward mint created: ${rewardMint.publicKey.toBase58()} `);
 
+    if (mode === "vault") {
+      const rewardVault = await getRewardVault(
+        rewardMint.publicKey,
+        rewardAuthority
+      );
+      const vaultTx = new web3.Transaction().add(
+        splToken.Token.createAssociatedTokenAccountInstruction(
+          splToken.ASSOCIATED_TOKEN_PROGRAM_ID,
+          splToken.TOKEN_PROGRAM_ID,
+          rewardMint.publicKey,
+          rewardVault,
+          rewardAuthority,
+          wallet.publicKey
+        )
+      );
+      await web3.sendAndConfirmTransaction(connection, vaultTx, [
+        walletKeyPair,
+      ]);
+      console.log(`Reward vault created: ${rewardVault.toBase58()}`);
+    }
+
     const initRewarderTxId = await solNftStakingProgram.rpc.initializeRewarder(
       rewarderBump,
       rewardAuthorityBump,
@@ -204,6 +522,10 @@ rewarderCommand
       parsedCreators,
       wallet.publicKey,
       false,
+      { [mode]: {} },
+      { [custody]: {} },
+      collectionStrategy,
+      new anchor.BN(pool),
       {
         accounts: {
           rewarder: rewarder,
@@ -212,6 +534,7 @@ rewarderCommand
           rewardMint: rewardMint.publicKey,
           systemProgram,
           rent: rentSysvar,
+          clock: clockSysvar,
         },
         signers: [walletKeyPair],
       }
@@ -223,4 +546,1289 @@ rewarderCommand
     );
   });
 
+rewarderCommand
+  .command("fund")
+  .requiredOption(
+    "-n, --name <string>",
+    "The name of the NFT collection the rewarder is for"
+  )
+  .requiredOption(
+    "-a, --amount <number>",
+    "The amount of reward tokens to send to the reward vault"
+  )
+  .action(async (directory: string, cmd: Command) => {
+    const { env, keypair, pool } = cmd.parent.opts();
+    const { name, amount } = cmd.opts();
+    const connection = new web3.Connection(web3.clusterApiUrl(env));
+    const walletKeyPair = loadWalletKey(keypair);
+    const wallet = new anchor.Wallet(walletKeyPair);
+
+    anchor.setProvider(
+      new anchor.Provider(connection, wallet, {
+        commitment: "confirmed",
+      })
+    );
+
+    const solNftStakingProgram = (await Program.at(
+      SOL_NFT_STAKING_PROGRAM_ID
+    )) as Program<SolNftStaking>;
+
+    const [rewarder, _] = await getRewarderAddress(name, pool);
+    const [rewardAuthority, __] = await getRewarderAuthority(name, rewarder);
+    const rewarderData =
+      await solNftStakingProgram.account.nftStakeRewarder.fetch(rewarder);
+    const rewardVault = await getRewardVault(
+      rewarderData.rewardMint,
+      rewardAuthority
+    );
+    const funderTokenAccount = await splToken.Token.getAssociatedTokenAddress(
+      splToken.ASSOCIATED_TOKEN_PROGRAM_ID,
+      splToken.TOKEN_PROGRAM_ID,
+      rewarderData.rewardMint,
+      wallet.publicKey
+    );
+
+    const fundTxId = await solNftStakingProgram.rpc.fundRewarder(
+      new anchor.BN(amount),
+      {
+        accounts: {
+          funder: wallet.publicKey,
+          rewarder,
+          rewardAuthority,
+          rewardVault,
+          funderTokenAccount,
+          tokenProgram: splToken.TOKEN_PROGRAM_ID,
+        },
+        signers: [walletKeyPair],
+      }
+    );
+
+    await connection.confirmTransaction(fundTxId, "confirmed");
+    console.log(
+      `Sent ${amount} tokens to ${rewardVault.toBase58()}: ${fundTxId}`
+    );
+  });
+
+rewarderCommand
+  .command("lockups")
+  .requiredOption(
+    "-n, --name <string>",
+    "The name of the NFT collection the rewarder is for"
+  )
+  .requiredOption(
+    "-t, --tiers <string>",
+    "Comma separated duration:multiplier pairs, duration in seconds and multiplier in basis points"
+  )
+  .action(async (directory: string, cmd: Command) => {
+    const { env, keypair, pool } = cmd.parent.opts();
+    const { name, tiers } = cmd.opts();
+    const connection = new web3.Connection(web3.clusterApiUrl(env));
+    const walletKeyPair = loadWalletKey(keypair);
+    const wallet = new anchor.Wallet(walletKeyPair);
+
+    anchor.setProvider(
+      new anchor.Provider(connection, wallet, {
+        commitment: "confirmed",
+      })
+    );
+
+    const solNftStakingProgram = (await Program.at(
+      SOL_NFT_STAKING_PROGRAM_ID
+    )) as Program<SolNftStaking>;
+
+    const [rewarder, _] = await getRewarderAddress(name, pool);
+
+    const updateTxId = await solNftStakingProgram.rpc.updateLockupTiers(
+      parseLockupTiers(tiers),
+      {
+        accounts: {
+          rewarder,
+          authority: wallet.publicKey,
+        },
+        signers: [walletKeyPair],
+      }
+    );
+
+    await connection.confirmTransaction(updateTxId, "confirmed");
+    const rewarderData =
+      await solNftStakingProgram.account.nftStakeRewarder.fetch(rewarder);
+    printRewarder(rewarder, rewarderData);
+  });
+
+rewarderCommand
+  .command("unbonding")
+  .requiredOption(
+    "-n, --name <string>",
+    "The name of the NFT collection the rewarder is for"
+  )
+  .requiredOption(
+    "-p, --period <number>",
+    "The number of seconds unstaked NFTs wait before they can be withdrawn"
+  )
+  .option(
+    "-f, --penalty <number>",
+    "The share of an NFT's lifetime rewards in basis points forfeited by withdrawing early. 0 disallows early withdrawals",
+    "0"
+  )
+  .action(async (directory: string, cmd: Command) => {
+    const { env, keypair, pool } = cmd.parent.opts();
+    const { name, period, penalty } = cmd.opts();
+    const connection = new web3.Connection(web3.clusterApiUrl(env));
+    const walletKeyPair = loadWalletKey(keypair);
+    const wallet = new anchor.Wallet(walletKeyPair);
+
+    anchor.setProvider(
+      new anchor.Provider(connection, wallet, {
+        commitment: "confirmed",
+      })
+    );
+
+    const solNftStakingProgram = (await Program.at(
+      SOL_NFT_STAKING_PROGRAM_ID
+    )) as Program<SolNftStaking>;
+
+    const [rewarder, _] = await getRewarderAddress(name, pool);
+
+    const updateTxId = await solNftStakingProgram.rpc.updateUnbonding(
+      new anchor.BN(period),
+      parseInt(penalty),
+      {
+        accounts: {
+          rewarder,
+          authority: wallet.publicKey,
+        },
+        signers: [walletKeyPair],
+      }
+    );
+
+    await connection.confirmTransaction(updateTxId, "confirmed");
+    const rewarderData =
+      await solNftStakingProgram.account.nftStakeRewarder.fetch(rewarder);
+    printRewarder(rewarder, rewarderData);
+  });
+
+rewarderCommand
+  .command("weights")
+  .requiredOption(
+    "-n, --name <string>",
+    "The name of the NFT collection the rewarder is for"
+  )
+  .requiredOption(
+    "-r, --root <string>",
+    "The hex encoded Merkle root of (mint, weight) pairs. All zeros to have every NFT earn at 1x"
+  )
+  .action(async (directory: string, cmd: Command) => {
+    const { env, keypair, pool } = cmd.parent.opts();
+    const { name, root } = cmd.opts();
+    const connection = new web3.Connection(web3.clusterApiUrl(env));
+    const walletKeyPair = loadWalletKey(keypair);
+    const wallet = new anchor.Wallet(walletKeyPair);
+
+    const weightsRoot = parseMerkleRoot(root);
+
+    anchor.setProvider(
+      new anchor.Provider(connection, wallet, {
+        commitment: "confirmed",
+      })
+    );
+
+    const solNftStakingProgram = (await Program.at(
+      SOL_NFT_STAKING_PROGRAM_ID
+    )) as Program<SolNftStaking>;
+
+    const [rewarder, _] = await getRewarderAddress(name, pool);
+
+    const updateTxId = await solNftStakingProgram.rpc.updateWeightsRoot(
+      weightsRoot,
+      {
+        accounts: {
+          rewarder,
+          authority: wallet.publicKey,
+        },
+        signers: [walletKeyPair],
+      }
+    );
+
+    await connection.confirmTransaction(updateTxId, "confirmed");
+    const rewarderData =
+      await solNftStakingProgram.account.nftStakeRewarder.fetch(rewarder);
+    printRewarder(rewarder, rewarderData);
+  });
+
+rewarderCommand
+  .command("policy")
+  .requiredOption(
+    "-n, --name <string>",
+    "The name of the NFT collection the rewarder is for"
+  )
+  .option("--no-updateAuthority", "Don't check the NFT's update authority")
+  .option("--no-collection", "Don't check the NFT belongs to the collection")
+  .option("--no-creators", "Don't check the NFT's creators")
+  .option(
+    "-m, --creatorMatch <string>",
+    "'all' to require exactly the rewarder's creators, 'any' to require at least one of them or 'anyVerified' to require at least one of their addresses as a verified creator",
+    "all"
+  )
+  .option(
+    "-v, --verifiedOnly",
+    "Only count verified creators towards a match",
+    false
+  )
+  .option(
+    "-u, --updateAuthorities <string>",
+    "Comma separated update authorities allowed alongside the rewarder's update authority",
+    ""
+  )
+  .action(async (directory: string, cmd: Command) => {
+    const { env, keypair, pool } = cmd.parent.opts();
+    const {
+      name,
+      updateAuthority,
+      collection,
+      creators,
+      creatorMatch,
+      verifiedOnly,
+      updateAuthorities,
+    } = cmd.opts();
+    if (!["all", "any", "anyVerified"].includes(creatorMatch)) {
+      console.log("creatorMatch must be one of 'all', 'any' or 'anyVerified'");
+      return;
+    }
+    const connection = new web3.Connection(web3.clusterApiUrl(env));
+    const walletKeyPair = loadWalletKey(keypair);
+    const wallet = new anchor.Wallet(walletKeyPair);
+
+    anchor.setProvider(
+      new anchor.Provider(connection, wallet, {
+        commitment: "confirmed",
+      })
+    );
+
+    const solNftStakingProgram = (await Program.at(
+      SOL_NFT_STAKING_PROGRAM_ID
+    )) as Program<SolNftStaking>;
+
+    const [rewarder, _] = await getRewarderAddress(name, pool);
+    const metadataPolicy = {
+      checkUpdateAuthority: updateAuthority,
+      checkCollection: collection,
+      checkCreators: creators,
+      creatorMatch: { [creatorMatch]: {} },
+      verifiedCreatorsOnly: verifiedOnly,
+    };
+    const additionalUpdateAuthorities = updateAuthorities
+      .split(",")
+      .filter((authority) => authority !== "")
+      .map((authority) => new web3.PublicKey(authority));
+
+    const updateTxId = await solNftStakingProgram.rpc.updateMetadataPolicy(
+      metadataPolicy,
+      additionalUpdateAuthorities,
+      {
+        accounts: {
+          rewarder,
+          authority: wallet.publicKey,
+        },
+        signers: [walletKeyPair],
+      }
+    );
+
+    await connection.confirmTransaction(updateTxId, "confirmed");
+    const rewarderData =
+      await solNftStakingProgram.account.nftStakeRewarder.fetch(rewarder);
+    printRewarder(rewarder, rewarderData);
+  });
+
+rewarderCommand
+  .command("allowlist")
+  .requiredOption(
+    "-n, --name <string>",
+    "The name of the NFT collection the rewarder is for"
+  )
+  .requiredOption(
+    "-r, --root <string>",
+    "The hex encoded Merkle root of the mints allowed to be staked. All zeros to allow any mint"
+  )
+  .action(async (directory: string, cmd: Command) => {
+    const { env, keypair, pool } = cmd.parent.opts();
+    const { name, root } = cmd.opts();
+    const connection = new web3.Connection(web3.clusterApiUrl(env));
+    const walletKeyPair = loadWalletKey(keypair);
+    const wallet = new anchor.Wallet(walletKeyPair);
+
+    const allowlistRoot = parseMerkleRoot(root);
+
+    anchor.setProvider(
+      new anchor.Provider(connection, wallet, {
+        commitment: "confirmed",
+      })
+    );
+
+    const solNftStakingProgram = (await Program.at(
+      SOL_NFT_STAKING_PROGRAM_ID
+    )) as Program<SolNftStaking>;
+
+    const [rewarder, _] = await getRewarderAddress(name, pool);
+
+    const updateTxId = await solNftStakingProgram.rpc.updateAllowlistRoot(
+      allowlistRoot,
+      {
+        accounts: {
+          rewarder,
+          authority: wallet.publicKey,
+        },
+        signers: [walletKeyPair],
+      }
+    );
+
+    await connection.confirmTransaction(updateTxId, "confirmed");
+    const rewarderData =
+      await solNftStakingProgram.account.nftStakeRewarder.fetch(rewarder);
+    printRewarder(rewarder, rewarderData);
+  });
+
+rewarderCommand
+  .command("rules")
+  .requiredOption(
+    "-n, --name <string>",
+    "The name of the NFT collection the rewarder is for"
+  )
+  .option(
+    "-c, --creators <path>",
+    "the path to a json array of nft creator objects to replace the rewarder's creators"
+  )
+  .option(
+    "-u, --updateAuthority <string>",
+    "The update authority staked NFTs must have"
+  )
+  .option(
+    "-v, --verifiedCollection <string>",
+    "The mint of a Metaplex collection that staked NFTs must be verified members of"
+  )
+  .option(
+    "-p, --namePrefix",
+    "Match staked NFTs on the collection name prefix instead of a verified collection"
+  )
+  .option(
+    "-m, --enforceMetadata <boolean>",
+    "'true' to check the metadata of staked NFTs, 'false' to stop checking it"
+  )
+  .action(async (directory: string, cmd: Command) => {
+    const { env, keypair, pool } = cmd.parent.opts();
+    const {
+      name,
+      creators,
+      updateAuthority,
+      verifiedCollection,
+      namePrefix,
+      enforceMetadata,
+    } = cmd.opts();
+    if (verifiedCollection && namePrefix) {
+      console.log("only one of verifiedCollection and namePrefix can be set");
+      return;
+    }
+    if (
+      enforceMetadata !== undefined &&
+      !["true", "false"].includes(enforceMetadata)
+    ) {
+      console.log("enforceMetadata must be either 'true' or 'false'");
+      return;
+    }
+    const connection = new web3.Connection(web3.clusterApiUrl(env));
+    const walletKeyPair = loadWalletKey(keypair);
+    const wallet = new anchor.Wallet(walletKeyPair);
+
+    anchor.setProvider(
+      new anchor.Provider(connection, wallet, {
+        commitment: "confirmed",
+      })
+    );
+
+    const solNftStakingProgram = (await Program.at(
+      SOL_NFT_STAKING_PROGRAM_ID
+    )) as Program<SolNftStaking>;
+
+    const [rewarder, _] = await getRewarderAddress(name, pool);
+    const accounts = {
+      rewarder,
+      authority: wallet.publicKey,
+    };
+
+    const updateTx = new web3.Transaction();
+    if (creators) {
+      updateTx.add(
+        solNftStakingProgram.instruction.updateCreators(
+          loadCreators(creators),
+          {
+            accounts: {
+              ...accounts,
+              systemProgram,
+              rent: rentSysvar,
+            },
+          }
+        )
+      );
+    }
+    if (updateAuthority) {
+      updateTx.add(
+        solNftStakingProgram.instruction.updateAllowedUpdateAuthority(
+          new web3.PublicKey(updateAuthority),
+          { accounts }
+        )
+      );
+    }
+    if (verifiedCollection || namePrefix) {
+      const collectionStrategy = verifiedCollection
+        ? {
+            verifiedCollection: {
+              mint: new web3.PublicKey(verifiedCollection),
+            },
+          }
+        : { namePrefix: {} };
+      updateTx.add(
+        solNftStakingProgram.instruction.updateCollectionStrategy(
+          collectionStrategy,
+          { accounts }
+        )
+      );
+    }
+    if (enforceMetadata !== undefined) {
+      updateTx.add(
+        solNftStakingProgram.instruction.updateEnforceMetadata(
+          enforceMetadata === "true",
+          { accounts }
+        )
+      );
+    }
+    if (updateTx.instructions.length === 0) {
+      console.log("nothing to update");
+      return;
+    }
+
+    await web3.sendAndConfirmTransaction(connection, updateTx, [
+      walletKeyPair,
+    ]);
+    const rewarderData =
+      await solNftStakingProgram.account.nftStakeRewarder.fetch(rewarder);
+    printRewarder(rewarder, rewarderData);
+  });
+
+rewarderCommand
+  .command("propose-authority")
+  .requiredOption(
+    "-n, --name <string>",
+    "The name of the NFT collection the rewarder is for"
+  )
+  .requiredOption(
+    "-a, --authority <string>",
+    "The key to hand the rewarder to. It has to accept with 'rewarder accept-authority'"
+  )
+  .action(async (directory: string, cmd: Command) => {
+    const { env, keypair, pool } = cmd.parent.opts();
+    const { name, authority } = cmd.opts();
+    const connection = new web3.Connection(web3.clusterApiUrl(env));
+    const walletKeyPair = loadWalletKey(keypair);
+    const wallet = new anchor.Wallet(walletKeyPair);
+
+    anchor.setProvider(
+      new anchor.Provider(connection, wallet, {
+        commitment: "confirmed",
+      })
+    );
+
+    const solNftStakingProgram = (await Program.at(
+      SOL_NFT_STAKING_PROGRAM_ID
+    )) as Program<SolNftStaking>;
+
+    const [rewarder, _] = await getRewarderAddress(name, pool);
+
+    const proposeTxId = await solNftStakingProgram.rpc.proposeAuthority(
+      new web3.PublicKey(authority),
+      {
+        accounts: {
+          rewarder,
+          authority: wallet.publicKey,
+        },
+        signers: [walletKeyPair],
+      }
+    );
+
+    await connection.confirmTransaction(proposeTxId, "confirmed");
+    const rewarderData =
+      await solNftStakingProgram.account.nftStakeRewarder.fetch(rewarder);
+    printRewarder(rewarder, rewarderData);
+  });
+
+rewarderCommand
+  .command("accept-authority")
+  .requiredOption(
+    "-n, --name <string>",
+    "The name of the NFT collection the rewarder is for"
+  )
+  .action(async (directory: string, cmd: Command) => {
+    const { env, keypair, pool } = cmd.parent.opts();
+    const { name } = cmd.opts();
+    const connection = new web3.Connection(web3.clusterApiUrl(env));
+    const walletKeyPair = loadWalletKey(keypair);
+    const wallet = new anchor.Wallet(walletKeyPair);
+
+    anchor.setProvider(
+      new anchor.Provider(connection, wallet, {
+        commitment: "confirmed",
+      })
+    );
+
+    const solNftStakingProgram = (await Program.at(
+      SOL_NFT_STAKING_PROGRAM_ID
+    )) as Program<SolNftStaking>;
+
+    const [rewarder, _] = await getRewarderAddress(name, pool);
+
+    const acceptTxId = await solNftStakingProgram.rpc.acceptAuthority({
+      accounts: {
+        rewarder,
+        pendingAuthority: wallet.publicKey,
+      },
+      signers: [walletKeyPair],
+    });
+
+    await connection.confirmTransaction(acceptTxId, "confirmed");
+    const rewarderData =
+      await solNftStakingProgram.account.nftStakeRewarder.fetch(rewarder);
+    printRewarder(rewarder, rewarderData);
+  });
+
+rewarderCommand
+  .command("schedule")
+  .requiredOption(
+    "-n, --name <string>",
+    "The name of the NFT collection the rewarder is for"
+  )
+  .option(
+    "-s, --startTime <number>",
+    "The unix timestamp rewards start accruing from, 0 for no start time",
+    "0"
+  )
+  .option(
+    "-e, --endTime <number>",
+    "The unix timestamp rewards stop accruing at, 0 for no end time",
+    "0"
+  )
+  .option(
+    "-i, --halvingInterval <number>",
+    "The number of seconds after which the reward rate halves, 0 to never halve",
+    "0"
+  )
+  .action(async (directory: string, cmd: Command) => {
+    const { env, keypair, pool } = cmd.parent.opts();
+    const { name, startTime, endTime, halvingInterval } = cmd.opts();
+    const connection = new web3.Connection(web3.clusterApiUrl(env));
+    const walletKeyPair = loadWalletKey(keypair);
+    const wallet = new anchor.Wallet(walletKeyPair);
+
+    anchor.setProvider(
+      new anchor.Provider(connection, wallet, {
+        commitment: "confirmed",
+      })
+    );
+
+    const solNftStakingProgram = (await Program.at(
+      SOL_NFT_STAKING_PROGRAM_ID
+    )) as Program<SolNftStaking>;
+
+    const [rewarder, _] = await getRewarderAddress(name, pool);
+
+    const updateTxId = await solNftStakingProgram.rpc.updateSchedule(
+      {
+        startTime: new anchor.BN(startTime),
+        endTime: new anchor.BN(endTime),
+        halvingInterval: new anchor.BN(halvingInterval),
+      },
+      {
+        accounts: {
+          rewarder,
+          authority: wallet.publicKey,
+          clock: clockSysvar,
+        },
+        signers: [walletKeyPair],
+      }
+    );
+
+    await connection.confirmTransaction(updateTxId, "confirmed");
+    const rewarderData =
+      await solNftStakingProgram.account.nftStakeRewarder.fetch(rewarder);
+    printRewarder(rewarder, rewarderData);
+  });
+
+rewarderCommand
+  .command("cap")
+  .requiredOption(
+    "-n, --name <string>",
+    "The name of the NFT collection the rewarder is for"
+  )
+  .requiredOption(
+    "-c, --cap <number>",
+    "The most reward tokens the rewarder will ever pay out, 0 for no cap"
+  )
+  .action(async (directory: string, cmd: Command) => {
+    const { env, keypair, pool } = cmd.parent.opts();
+    const { name, cap } = cmd.opts();
+    const connection = new web3.Connection(web3.clusterApiUrl(env));
+    const walletKeyPair = loadWalletKey(keypair);
+    const wallet = new anchor.Wallet(walletKeyPair);
+
+    anchor.setProvider(
+      new anchor.Provider(connection, wallet, {
+        commitment: "confirmed",
+      })
+    );
+
+    const solNftStakingProgram = (await Program.at(
+      SOL_NFT_STAKING_PROGRAM_ID
+    )) as Program<SolNftStaking>;
+
+    const [rewarder, _] = await getRewarderAddress(name, pool);
+
+    const updateTxId = await solNftStakingProgram.rpc.updateEmissionCap(
+      new anchor.BN(cap),
+      {
+        accounts: {
+          rewarder,
+          authority: wallet.publicKey,
+        },
+        signers: [walletKeyPair],
+      }
+    );
+
+    await connection.confirmTransaction(updateTxId, "confirmed");
+    const rewarderData =
+      await solNftStakingProgram.account.nftStakeRewarder.fetch(rewarder);
+    printRewarder(rewarder, rewarderData);
+  });
+
+rewarderCommand
+  .command("add-stream")
+  .requiredOption(
+    "-n, --name <string>",
+    "The name of the NFT collection the rewarder is for"
+  )
+  .requiredOption(
+    "-t, --rewardMint <string>",
+    "The mint of the extra reward token"
+  )
+  .requiredOption(
+    "-r, --rewardRate <number>",
+    "The number reward per second per nft staked for the stream"
+  )
+  .option(
+    "-m, --mode <string>",
+    "How the stream's rewards are paid out. 'mint' if the stream's reward authority is the mint authority or 'vault' to transfer from a pre-funded vault",
+    "vault"
+  )
+  .action(async (directory: string, cmd: Command) => {
+    const { env, keypair, pool } = cmd.parent.opts();
+    const { name, rewardMint, rewardRate, mode } = cmd.opts();
+    if (mode !== "mint" && mode !== "vault") {
+      console.log("mode must be either 'mint' or 'vault'");
+      return;
+    }
+    const connection = new web3.Connection(web3.clusterApiUrl(env));
+    const walletKeyPair = loadWalletKey(keypair);
+    const wallet = new anchor.Wallet(walletKeyPair);
+
+    anchor.setProvider(
+      new anchor.Provider(connection, wallet, {
+        commitment: "confirmed",
+      })
+    );
+
+    const solNftStakingProgram = (await Program.at(
+      SOL_NFT_STAKING_PROGRAM_ID
+    )) as Program<SolNftStaking>;
+
+    const mint = new web3.PublicKey(rewardMint);
+    const [rewarder, _] = await getRewarderAddress(name, pool);
+    const [rewardAuthority, rewardAuthorityBump] = await getStreamAuthority(
+      name,
+      rewarder,
+      mint
+    );
+
+    if (mode === "vault") {
+      const rewardVault = await getRewardVault(mint, rewardAuthority);
+      if ((await connection.getAccountInfo(rewardVault)) === null) {
+        const vaultTx = new web3.Transaction().add(
+          splToken.Token.createAssociatedTokenAccountInstruction(
+            splToken.ASSOCIATED_TOKEN_PROGRAM_ID,
+            splToken.TOKEN_PROGRAM_ID,
+            mint,
+            rewardVault,
+            rewardAuthority,
+            wallet.publicKey
+          )
+        );
+        await web3.sendAndConfirmTransaction(connection, vaultTx, [
+          walletKeyPair,
+        ]);
+      }
+      console.log(`Stream reward vault: ${rewardVault.toBase58()}`);
+    } else {
+      console.log(
+        `The stream's reward authority ${rewardAuthority.toBase58()} must be the mint authority`
+      );
+    }
+
+    const addTxId = await solNftStakingProgram.rpc.addRewardStream(
+      rewardAuthorityBump,
+      new anchor.BN(rewardRate),
+      { [mode]: {} },
+      {
+        accounts: {
+          rewarder,
+          authority: wallet.publicKey,
+          rewardAuthority,
+          rewardMint: mint,
+          clock: clockSysvar,
+        },
+        signers: [walletKeyPair],
+      }
+    );
+
+    await connection.confirmTransaction(addTxId, "confirmed");
+    const rewarderData =
+      await solNftStakingProgram.account.nftStakeRewarder.fetch(rewarder);
+    printRewarder(rewarder, rewarderData);
+  });
+
+rewarderCommand
+  .command("stream-rate")
+  .requiredOption(
+    "-n, --name <string>",
+    "The name of the NFT collection the rewarder is for"
+  )
+  .requiredOption(
+    "-i, --index <number>",
+    "The index of the stream in the rewarder's reward streams"
+  )
+  .requiredOption(
+    "-r, --rewardRate <number>",
+    "The number reward per second per nft staked for the stream"
+  )
+  .action(async (directory: string, cmd: Command) => {
+    const { env, keypair, pool } = cmd.parent.opts();
+    const { name, index, rewardRate } = cmd.opts();
+    const connection = new web3.Connection(web3.clusterApiUrl(env));
+    const walletKeyPair = loadWalletKey(keypair);
+    const wallet = new anchor.Wallet(walletKeyPair);
+
+    anchor.setProvider(
+      new anchor.Provider(connection, wallet, {
+        commitment: "confirmed",
+      })
+    );
+
+    const solNftStakingProgram = (await Program.at(
+      SOL_NFT_STAKING_PROGRAM_ID
+    )) as Program<SolNftStaking>;
+
+    const [rewarder, _] = await getRewarderAddress(name, pool);
+
+    const updateTxId = await solNftStakingProgram.rpc.updateStreamRate(
+      parseInt(index),
+      new anchor.BN(rewardRate),
+      {
+        accounts: {
+          rewarder,
+          authority: wallet.publicKey,
+          clock: clockSysvar,
+        },
+        signers: [walletKeyPair],
+      }
+    );
+
+    await connection.confirmTransaction(updateTxId, "confirmed");
+    const rewarderData =
+      await solNftStakingProgram.account.nftStakeRewarder.fetch(rewarder);
+    printRewarder(rewarder, rewarderData);
+  });
+
+rewarderCommand
+  .command("pause")
+  .requiredOption(
+    "-n, --name <string>",
+    "The name of the NFT collection the rewarder is for"
+  )
+  .option("-s, --staking", "Pause staking")
+  .option("-c, --claiming", "Pause claiming")
+  .option("-a, --accrual", "Pause reward accrual")
+  .action(async (directory: string, cmd: Command) => {
+    const { env, keypair, pool } = cmd.parent.opts();
+    const { name, staking, claiming, accrual } = cmd.opts();
+    const connection = new web3.Connection(web3.clusterApiUrl(env));
+    const walletKeyPair = loadWalletKey(keypair);
+    const wallet = new anchor.Wallet(walletKeyPair);
+
+    anchor.setProvider(
+      new anchor.Provider(connection, wallet, {
+        commitment: "confirmed",
+      })
+    );
+
+    const solNftStakingProgram = (await Program.at(
+      SOL_NFT_STAKING_PROGRAM_ID
+    )) as Program<SolNftStaking>;
+
+    const [rewarder, _] = await getRewarderAddress(name, pool);
+
+    // flags that aren't passed are unpaused
+    const pauseTxId = await solNftStakingProgram.rpc.setPaused(
+      {
+        staking: !!staking,
+        claiming: !!claiming,
+        accrual: !!accrual,
+      },
+      {
+        accounts: {
+          rewarder,
+          authority: wallet.publicKey,
+          clock: clockSysvar,
+        },
+        signers: [walletKeyPair],
+      }
+    );
+
+    await connection.confirmTransaction(pauseTxId, "confirmed");
+    const rewarderData =
+      await solNftStakingProgram.account.nftStakeRewarder.fetch(rewarder);
+    printRewarder(rewarder, rewarderData);
+  });
+
+rewarderCommand
+  .command("block")
+  .requiredOption(
+    "-n, --name <string>",
+    "The name of the NFT collection the rewarder is for"
+  )
+  .requiredOption("-m, --mint <string>", "The NFT mint to block")
+  .action(async (directory: string, cmd: Command) => {
+    const { env, keypair, pool } = cmd.parent.opts();
+    const { name, mint } = cmd.opts();
+    const connection = new web3.Connection(web3.clusterApiUrl(env));
+    const walletKeyPair = loadWalletKey(keypair);
+    const wallet = new anchor.Wallet(walletKeyPair);
+
+    anchor.setProvider(
+      new anchor.Provider(connection, wallet, {
+        commitment: "confirmed",
+      })
+    );
+
+    const solNftStakingProgram = (await Program.at(
+      SOL_NFT_STAKING_PROGRAM_ID
+    )) as Program<SolNftStaking>;
+
+    const nftMint = new web3.PublicKey(mint);
+    const [rewarder, _] = await getRewarderAddress(name, pool);
+    const [blockedMint, blockedMintBump] = await getBlockedMintAddress(
+      name,
+      rewarder,
+      nftMint
+    );
+
+    // a staked NFT stops earning, which needs its stake account and receipt
+    const receipt = await findStakeReceipt(
+      solNftStakingProgram,
+      rewarder,
+      nftMint
+    );
+    const remainingAccounts = receipt
+      ? [
+          {
+            pubkey: receipt.account.stakeAccount,
+            isWritable: true,
+            isSigner: false,
+          },
+          { pubkey: receipt.publicKey, isWritable: true, isSigner: false },
+        ]
+      : [];
+
+    const blockTxId = await solNftStakingProgram.rpc.blockMint(
+      blockedMintBump,
+      {
+        accounts: {
+          rewarder,
+          authority: wallet.publicKey,
+          nftMint,
+          blockedMint,
+          systemProgram,
+          rent: rentSysvar,
+          clock: clockSysvar,
+        },
+        remainingAccounts,
+        signers: [walletKeyPair],
+      }
+    );
+
+    await connection.confirmTransaction(blockTxId, "confirmed");
+    console.log(`Blocked ${nftMint.toBase58()} with ${blockedMint.toBase58()}`);
+  });
+
+rewarderCommand
+  .command("unblock")
+  .requiredOption(
+    "-n, --name <string>",
+    "The name of the NFT collection the rewarder is for"
+  )
+  .requiredOption("-m, --mint <string>", "The NFT mint to unblock")
+  .action(async (directory: string, cmd: Command) => {
+    const { env, keypair, pool } = cmd.parent.opts();
+    const { name, mint } = cmd.opts();
+    const connection = new web3.Connection(web3.clusterApiUrl(env));
+    const walletKeyPair = loadWalletKey(keypair);
+    const wallet = new anchor.Wallet(walletKeyPair);
+
+    anchor.setProvider(
+      new anchor.Provider(connection, wallet, {
+        commitment: "confirmed",
+      })
+    );
+
+    const solNftStakingProgram = (await Program.at(
+      SOL_NFT_STAKING_PROGRAM_ID
+    )) as Program<SolNftStaking>;
+
+    const nftMint = new web3.PublicKey(mint);
+    const [rewarder, _] = await getRewarderAddress(name, pool);
+    const [blockedMint, __] = await getBlockedMintAddress(
+      name,
+      rewarder,
+      nftMint
+    );
+
+    // an NFT that was staked when it was blocked starts earning again
+    const { stakeAccount } =
+      await solNftStakingProgram.account.blockedMint.fetch(blockedMint);
+    const remainingAccounts = [];
+    if (!stakeAccount.equals(web3.PublicKey.default)) {
+      const [stakeReceipt, ___] = await getStakeReceiptAddress(
+        name,
+        stakeAccount,
+        nftMint
+      );
+      remainingAccounts.push(
+        { pubkey: stakeAccount, isWritable: true, isSigner: false },
+        { pubkey: stakeReceipt, isWritable: true, isSigner: false }
+      );
+    }
+
+    const unblockTxId = await solNftStakingProgram.rpc.unblockMint({
+      accounts: {
+        rewarder,
+        authority: wallet.publicKey,
+        blockedMint,
+        clock: clockSysvar,
+      },
+      remainingAccounts,
+      signers: [walletKeyPair],
+    });
+
+    await connection.confirmTransaction(unblockTxId, "confirmed");
+    console.log(`Unblocked ${nftMint.toBase58()}`);
+  });
+
+rewarderCommand
+  .command("force-unstake")
+  .requiredOption(
+    "-n, --name <string>",
+    "The name of the NFT collection the rewarder is for"
+  )
+  .requiredOption("-m, --mint <string>", "The blocked NFT mint to unstake")
+  .requiredOption(
+    "-t, --tokenAccount <string>",
+    "The token account of the NFT. With escrow custody any token account of the owner for the mint"
+  )
+  .action(async (directory: string, cmd: Command) => {
+    const { env, keypair, pool } = cmd.parent.opts();
+    const { name, mint, tokenAccount } = cmd.opts();
+    const connection = new web3.Connection(web3.clusterApiUrl(env));
+    const walletKeyPair = loadWalletKey(keypair);
+    const wallet = new anchor.Wallet(walletKeyPair);
+
+    anchor.setProvider(
+      new anchor.Provider(connection, wallet, {
+        commitment: "confirmed",
+      })
+    );
+
+    const solNftStakingProgram = (await Program.at(
+      SOL_NFT_STAKING_PROGRAM_ID
+    )) as Program<SolNftStaking>;
+
+    const nftMint = new web3.PublicKey(mint);
+    const [rewarder, _] = await getRewarderAddress(name, pool);
+    const [blockedMint, __] = await getBlockedMintAddress(
+      name,
+      rewarder,
+      nftMint
+    );
+
+    const receipt = await findStakeReceipt(
+      solNftStakingProgram,
+      rewarder,
+      nftMint
+    );
+    if (!receipt) {
+      throw new Error(`${nftMint.toBase58()} is not staked`);
+    }
+    const stakeAccount = receipt.account.stakeAccount;
+    const stakeAccountData =
+      await solNftStakingProgram.account.nftStakeAccount.fetch(stakeAccount);
+
+    const nftVault = await splToken.Token.getAssociatedTokenAddress(
+      splToken.ASSOCIATED_TOKEN_PROGRAM_ID,
+      splToken.TOKEN_PROGRAM_ID,
+      nftMint,
+      stakeAccount,
+      true
+    );
+
+    const unstakeTxId = await solNftStakingProgram.rpc.forceUnstake({
+      accounts: {
+        rewarder,
+        authority: wallet.publicKey,
+        blockedMint,
+        owner: stakeAccountData.owner,
+        stakeAccount,
+        stakeReceipt: receipt.publicKey,
+        nftMint,
+        nftTokenAccount: new web3.PublicKey(tokenAccount),
+        nftVault,
+        nftEdition: await getEditionAddress(nftMint),
+        tokenProgram: splToken.TOKEN_PROGRAM_ID,
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        clock: clockSysvar,
+      },
+      signers: [walletKeyPair],
+    });
+
+    await connection.confirmTransaction(unstakeTxId, "confirmed");
+    console.log(
+      `Returned ${nftMint.toBase58()} to ${stakeAccountData.owner.toBase58()}`
+    );
+  });
+
+rewarderCommand
+  .command("migrate")
+  .requiredOption(
+    "-n, --name <string>",
+    "The name of the NFT collection the rewarder is for"
+  )
+  .action(async (directory: string, cmd: Command) => {
+    const { env, keypair, pool } = cmd.parent.opts();
+    const { name } = cmd.opts();
+    const connection = new web3.Connection(web3.clusterApiUrl(env));
+    const walletKeyPair = loadWalletKey(keypair);
+    const wallet = new anchor.Wallet(walletKeyPair);
+
+    anchor.setProvider(
+      new anchor.Provider(connection, wallet, {
+        commitment: "confirmed",
+      })
+    );
+
+    const solNftStakingProgram = (await Program.at(
+      SOL_NFT_STAKING_PROGRAM_ID
+    )) as Program<SolNftStaking>;
+
+    const [rewarder, _] = await getRewarderAddress(name, pool);
+
+    const migrateTxId = await solNftStakingProgram.rpc.migrateRewarder({
+      accounts: {
+        rewarder,
+        payer: wallet.publicKey,
+        systemProgram,
+        rent: rentSysvar,
+        clock: clockSysvar,
+      },
+      signers: [walletKeyPair],
+    });
+
+    await connection.confirmTransaction(migrateTxId, "confirmed");
+    console.log(`Migrated rewarder ${rewarder.toBase58()}`);
+  });
+
+const stakeCommand = cliCommand("stake");
+
+stakeCommand
+  .command("list")
+  .requiredOption(
+    "-n, --name <string>",
+    "The name of the NFT collection the rewarder is for"
+  )
+  .requiredOption("-o, --owner <string>", "The owner of the stake account")
+  .action(async (directory: string, cmd: Command) => {
+    const { env, pool } = cmd.parent.opts();
+    const { name, owner } = cmd.opts();
+    const connection = new web3.Connection(web3.clusterApiUrl(env));
+
+    anchor.setProvider(
+      new anchor.Provider(connection, null, { commitment: "confirmed" })
+    );
+
+    const solNftStakingProgram = (await Program.at(
+      SOL_NFT_STAKING_PROGRAM_ID
+    )) as Program<SolNftStaking>;
+
+    const [rewarder, _] = await getRewarderAddress(name, pool);
+    const [stakeAccount, __] = await getStakeAccountAddress(
+      name,
+      rewarder,
+      new web3.PublicKey(owner)
+    );
+
+    // the stake account is the first field of a receipt, right after the account discriminator
+    const receipts = await solNftStakingProgram.account.stakeReceipt.all([
+      { memcmp: { offset: 8, bytes: stakeAccount.toBase58() } },
+    ]);
+    console.log(`Stake account ${stakeAccount.toBase58()}`);
+    for (const receipt of receipts) {
+      const stakedAt = new Date(receipt.account.stakedAt.toNumber() * 1000);
+      const lockupEnd = new Date(receipt.account.lockupEnd.toNumber() * 1000);
+      console.log(
+        `${receipt.account.mint.toBase58()} staked at ${stakedAt.toISOString()} ` +
+          `with a ${receipt.account.multiplier / BASE_MULTIPLIER}x multiplier ` +
+          `and ${receipt.account.weight.toNumber() / BASE_MULTIPLIER}x weight, ` +
+          `locked until ${lockupEnd.toISOString()}`
+      );
+      if (!receipt.account.unbondingEnd.isZero()) {
+        const unbondingEnd = new Date(
+          receipt.account.unbondingEnd.toNumber() * 1000
+        );
+        console.log(`  unbonding until ${unbondingEnd.toISOString()}`);
+      }
+    }
+  });
+
+stakeCommand
+  .command("migrate")
+  .requiredOption(
+    "-n, --name <string>",
+    "The name of the NFT collection the rewarder is for"
+  )
+  .requiredOption("-o, --owner <string>", "The owner of the stake account")
+  .action(async (directory: string, cmd: Command) => {
+    const { env, keypair, pool } = cmd.parent.opts();
+    const { name, owner } = cmd.opts();
+    const connection = new web3.Connection(web3.clusterApiUrl(env));
+    const walletKeyPair = loadWalletKey(keypair);
+    const wallet = new anchor.Wallet(walletKeyPair);
+
+    anchor.setProvider(
+      new anchor.Provider(connection, wallet, {
+        commitment: "confirmed",
+      })
+    );
+
+    const solNftStakingProgram = (await Program.at(
+      SOL_NFT_STAKING_PROGRAM_ID
+    )) as Program<SolNftStaking>;
+
+    const [rewarder, _] = await getRewarderAddress(name, pool);
+    const [stakeAccount, __] = await getStakeAccountAddress(
+      name,
+      rewarder,
+      new web3.PublicKey(owner)
+    );
+
+    const migrateTxId = await solNftStakingProgram.rpc.migrateStakeAccount({
+      accounts: {
+        stakeAccount,
+        rewarder,
+        payer: wallet.publicKey,
+        systemProgram,
+        rent: rentSysvar,
+      },
+      signers: [walletKeyPair],
+    });
+
+    await connection.confirmTransaction(migrateTxId, "confirmed");
+    console.log(`Migrated stake account ${stakeAccount.toBase58()}`);
+  });
+
+stakeCommand
+  .command("migrate-nfts")
+  .requiredOption(
+    "-n, --name <string>",
+    "The name of the NFT collection the rewarder is for"
+  )
+  .action(async (directory: string, cmd: Command) => {
+    const { env, keypair, pool } = cmd.parent.opts();
+    const { name } = cmd.opts();
+    const connection = new web3.Connection(web3.clusterApiUrl(env));
+    const walletKeyPair = loadWalletKey(keypair);
+    const wallet = new anchor.Wallet(walletKeyPair);
+
+    anchor.setProvider(
+      new anchor.Provider(connection, wallet, {
+        commitment: "confirmed",
+      })
+    );
+
+    const solNftStakingProgram = (await Program.at(
+      SOL_NFT_STAKING_PROGRAM_ID
+    )) as Program<SolNftStaking>;
+
+    const [rewarder, _] = await getRewarderAddress(name, pool);
+    const [stakeAccount, __] = await getStakeAccountAddress(
+      name,
+      rewarder,
+      wallet.publicKey
+    );
+
+    // v0 transferred ownership of staked NFT token accounts to the stake account
+    const tokenAccounts = await connection.getParsedTokenAccountsByOwner(
+      stakeAccount,
+      { programId: splToken.TOKEN_PROGRAM_ID }
+    );
+    for (const { pubkey, account } of tokenAccounts.value) {
+      const { mint, tokenAmount } = account.data.parsed.info;
+      if (tokenAmount.amount !== "1") {
+        continue;
+      }
+
+      const nftMint = new web3.PublicKey(mint);
+      const [stakeReceipt, receiptBump] = await getStakeReceiptAddress(
+        name,
+        stakeAccount,
+        nftMint
+      );
+      if (await connection.getAccountInfo(stakeReceipt)) {
+        continue;
+      }
+
+      const migrateTxId = await solNftStakingProgram.rpc.migrateStakedNft(
+        receiptBump,
+        {
+          accounts: {
+            owner: wallet.publicKey,
+            rewarder,
+            stakeAccount,
+            stakeReceipt,
+            nftMint,
+            nftTokenAccount: pubkey,
+            systemProgram,
+            rent: rentSysvar,
+            clock: clockSysvar,
+          },
+          signers: [walletKeyPair],
+        }
+      );
+
+      await connection.confirmTransaction(migrateTxId, "confirmed");
+      console.log(
+        `Created stake receipt ${stakeReceipt.toBase58()} for ${mint}`
+      );
+    }
+  });
+
 cliProgram.parse(process.argv);